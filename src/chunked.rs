@@ -0,0 +1,326 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::noise::{NoiseMap3D, NoiseSource3D};
+use crate::noise_file::NoiseMapError;
+
+//-------------------------------------------------------------------Defining Chunk Coord-------------------------------------------------------------------//
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkCoord
+{
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+impl ChunkCoord
+{
+    pub fn new(x: i32, y: i32, z: i32) -> ChunkCoord
+    {
+        return ChunkCoord { x, y, z };
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Chunked Noise Map-------------------------------------------------------------------//
+// Unbounded map made of chunk_size cubed NoiseMap3D chunks, generated from the source the first time they are touched
+// and kept in an LRU cache of at most capacity chunks. Edited chunks are written to directory, when one is set, before they are evicted
+pub struct ChunkedNoiseMap<S>
+where
+    S: NoiseSource3D + Sync,
+{
+    pub source: S,
+    // Fixed once the map is made, every cached and saved chunk has this size
+    chunk_size: usize,
+    pub capacity: usize,
+    pub directory: Option<String>,
+    chunks: HashMap<ChunkCoord, Chunk>,
+    tick: u64,
+}
+
+struct Chunk
+{
+    map: NoiseMap3D,
+    last_used: u64,
+    dirty: bool,
+}
+
+impl<S> ChunkedNoiseMap<S>
+where
+    S: NoiseSource3D + Sync,
+{
+    pub fn new(source: S, chunk_size: usize, capacity: usize) -> Result<ChunkedNoiseMap<S>, NoiseMapError>
+    {
+        if chunk_size == 0 {
+            return Err(NoiseMapError::ZeroChunkSize);
+        }
+        return Ok(ChunkedNoiseMap { source, chunk_size, capacity, directory: None, chunks: HashMap::new(), tick: 0 });
+    }
+
+    pub fn chunk_size(&self) -> usize
+    {
+        return self.chunk_size;
+    }
+
+    // Chunk holding the world cell and the position of the cell inside it
+    pub fn locate(&self, x: i32, y: i32, z: i32) -> (ChunkCoord, [u32; 3])
+    {
+        let size = self.chunk_size as i32;
+        let coord = ChunkCoord::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        return (coord, [x.rem_euclid(size) as u32, y.rem_euclid(size) as u32, z.rem_euclid(size) as u32]);
+    }
+
+    pub fn get_value_at(&mut self, x: i32, y: i32, z: i32) -> Result<f32, NoiseMapError>
+    {
+        let (coord, [local_x, local_y, local_z]) = self.locate(x, y, z);
+        let chunk = self.chunk_mut(coord)?;
+        return Ok(chunk.map.get_value_at(local_x, local_y, local_z));
+    }
+
+    pub fn set_value_at(&mut self, x: i32, y: i32, z: i32, value: f32) -> Result<(), NoiseMapError>
+    {
+        let (coord, [local_x, local_y, local_z]) = self.locate(x, y, z);
+        let chunk = self.chunk_mut(coord)?;
+        chunk.map.set_value_at(local_x, local_y, local_z, value);
+        chunk.dirty = true;
+        Ok(())
+    }
+
+    pub fn get_chunk(&mut self, coord: ChunkCoord) -> Result<&NoiseMap3D, NoiseMapError>
+    {
+        return Ok(&self.chunk_mut(coord)?.map);
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool
+    {
+        return self.chunks.contains_key(&coord);
+    }
+
+    pub fn loaded_chunk_count(&self) -> usize
+    {
+        return self.chunks.len();
+    }
+
+    // Writes a cached chunk to directory whether or not it was edited
+    pub fn save_chunk(&mut self, coord: ChunkCoord) -> Result<(), NoiseMapError>
+    {
+        let path = self.chunk_path(coord)?;
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.map.save(path)?;
+            chunk.dirty = false;
+        }
+        Ok(())
+    }
+
+    // Replaces the cached copy of a chunk with the one saved in directory
+    pub fn load_chunk(&mut self, coord: ChunkCoord) -> Result<&NoiseMap3D, NoiseMapError>
+    {
+        let map = self.load_saved(coord, self.chunk_path(coord)?)?;
+        self.chunks.remove(&coord);
+        self.insert_chunk(coord, map)?;
+        return self.get_chunk(coord);
+    }
+
+    pub fn save_dirty(&mut self) -> Result<(), NoiseMapError>
+    {
+        let dirty: Vec<ChunkCoord> = self.chunks.iter().filter(|(_, chunk)| chunk.dirty).map(|(coord, _)| *coord).collect();
+        for coord in dirty {
+            self.save_chunk(coord)?;
+        }
+        Ok(())
+    }
+
+    fn chunk_mut(&mut self, coord: ChunkCoord) -> Result<&mut Chunk, NoiseMapError>
+    {
+        if !self.chunks.contains_key(&coord) {
+            let map = self.load_or_generate(coord)?;
+            self.insert_chunk(coord, map)?;
+        }
+
+        self.tick += 1;
+        let chunk = self.chunks.get_mut(&coord).unwrap();
+        chunk.last_used = self.tick;
+        return Ok(chunk);
+    }
+
+    // A chunk saved earlier wins over regenerating it, so edits survive eviction
+    fn load_or_generate(&self, coord: ChunkCoord) -> Result<NoiseMap3D, NoiseMapError>
+    {
+        if let Ok(path) = self.chunk_path(coord) {
+            if Path::new(&path).exists() {
+                return self.load_saved(coord, path);
+            }
+        }
+
+        let size = self.chunk_size;
+        let mut map = NoiseMap3D::from_values(size, size, size);
+        map.origin = self.chunk_origin(coord);
+        map.populate_with(&self.source);
+        return Ok(map);
+    }
+
+    // A saved chunk is only used if it has this map's chunk size and sits where coord's chunk does, anything else would be indexed out of bounds or in the wrong place
+    fn load_saved(&self, coord: ChunkCoord, path: String) -> Result<NoiseMap3D, NoiseMapError>
+    {
+        let map = NoiseMap3D::load(path.clone())?;
+        let dimensions = [map.width, map.height, map.depth];
+        let expected_origin = self.chunk_origin(coord);
+        if dimensions != [self.chunk_size; 3] || map.origin != expected_origin {
+            return Err(NoiseMapError::ChunkMismatch { path, dimensions, origin: map.origin, chunk_size: self.chunk_size, expected_origin });
+        }
+        return Ok(map);
+    }
+
+    fn chunk_origin(&self, coord: ChunkCoord) -> [f64; 3]
+    {
+        let size = self.chunk_size as f64;
+        return [coord.x as f64 * size, coord.y as f64 * size, coord.z as f64 * size];
+    }
+
+    fn insert_chunk(&mut self, coord: ChunkCoord, map: NoiseMap3D) -> Result<(), NoiseMapError>
+    {
+        while self.chunks.len() >= self.capacity.max(1) {
+            self.evict_oldest()?;
+        }
+        self.tick += 1;
+        self.chunks.insert(coord, Chunk { map, last_used: self.tick, dirty: false });
+        Ok(())
+    }
+
+    fn evict_oldest(&mut self) -> Result<(), NoiseMapError>
+    {
+        let Some(coord) = self.chunks.iter().min_by_key(|(_, chunk)| chunk.last_used).map(|(coord, _)| *coord) else {
+            return Ok(());
+        };
+        if self.chunks[&coord].dirty && self.directory.is_some() {
+            self.save_chunk(coord)?;
+        }
+        self.chunks.remove(&coord);
+        Ok(())
+    }
+
+    fn chunk_path(&self, coord: ChunkCoord) -> Result<String, NoiseMapError>
+    {
+        let directory = self.directory.as_ref().ok_or(NoiseMapError::NoChunkDirectory)?;
+        return Ok(format!("{}/chunk_{}_{}_{}.noise3d", directory, coord.x, coord.y, coord.z));
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::noise::{FractalMode, NoiseGenerator3D, NoiseValues};
+    use libnoise::prelude::*;
+
+    fn generator() -> NoiseGenerator3D<Perlin<3>>
+    {
+        let values = NoiseValues { octaves: 3, scale: 0.25, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+        return NoiseGenerator3D { source: Perlin::<3>::new(7), values };
+    }
+
+    #[test]
+    fn zero_chunk_size_is_rejected()
+    {
+        assert!(matches!(ChunkedNoiseMap::new(generator(), 0, 4), Err(NoiseMapError::ZeroChunkSize)));
+    }
+
+    // Chunks -1 and 0 along x have to join up like any two cells inside a chunk, and agree with one map covering both
+    #[test]
+    fn no_seam_between_negative_and_positive_chunks()
+    {
+        let mut chunked = ChunkedNoiseMap::new(generator(), 8, 8).unwrap();
+        let mut whole = NoiseMap3D::from_values(32, 1, 1);
+        whole.origin = [-16.0, 3.0, 2.0];
+        whole.populate_with(&generator());
+
+        let row: Vec<f32> = (-16..16).map(|x| chunked.get_value_at(x, 3, 2).unwrap()).collect();
+        assert_eq!(row, whole.buffer);
+
+        let steps: Vec<f32> = row.windows(2).map(|pair| (pair[1] - pair[0]).abs()).collect();
+        let seam = steps[15];
+        let largest_inside = steps.iter().enumerate().filter(|(index, _)| *index != 15).map(|(_, step)| *step).fold(0.0, f32::max);
+        assert!(seam <= largest_inside * 1.5, "seam step {} against {} inside the chunks", seam, largest_inside);
+
+        // 1024 cells is a whole number of 256 cell lattice periods at every octave of the generator,
+        // so the negative chunks have to repeat the positive ones that far along rather than a copy of cell 0
+        for x in -16..0 {
+            let (value, shifted) = (chunked.get_value_at(x, 3, 2).unwrap(), chunked.get_value_at(x + 1024, 3, 2).unwrap());
+            assert!((value - shifted).abs() < 1e-5, "x {}: {} != {}", x, value, shifted);
+        }
+    }
+
+    // Unique per process and test so parallel test runs never share chunk files
+    fn temp_directory(name: &str) -> String
+    {
+        let directory = std::env::temp_dir().join(format!("noise_project_chunks_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).unwrap();
+        return directory.to_str().unwrap().to_string();
+    }
+
+    #[test]
+    fn least_recently_used_chunk_is_evicted()
+    {
+        let mut chunked = ChunkedNoiseMap::new(generator(), 4, 2).unwrap();
+        let (a, b, c) = (ChunkCoord::new(0, 0, 0), ChunkCoord::new(1, 0, 0), ChunkCoord::new(0, -1, 0));
+        for coord in [a, b, a, c] {
+            chunked.get_chunk(coord).unwrap();
+        }
+        assert_eq!(chunked.loaded_chunk_count(), 2);
+        assert!(chunked.is_loaded(a));
+        assert!(!chunked.is_loaded(b));
+        assert!(chunked.is_loaded(c));
+    }
+
+    #[test]
+    fn edits_survive_eviction()
+    {
+        let directory = temp_directory("edits");
+        let mut chunked = ChunkedNoiseMap::new(generator(), 4, 1).unwrap();
+        chunked.directory = Some(directory.clone());
+
+        chunked.set_value_at(-3, 2, 1, 42.0).unwrap();
+        chunked.set_value_at(5, 0, 0, -7.0).unwrap();
+        assert!(!chunked.is_loaded(ChunkCoord::new(-1, 0, 0)));
+
+        let (first, second) = (chunked.get_value_at(-3, 2, 1).unwrap(), chunked.get_value_at(5, 0, 0).unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!((first, second), (42.0, -7.0));
+    }
+
+    // Without a directory there is nowhere to keep an edited chunk, so eviction drops the edit and the chunk is generated again
+    #[test]
+    fn dirty_chunks_without_a_directory_are_dropped()
+    {
+        let mut chunked = ChunkedNoiseMap::new(generator(), 4, 1).unwrap();
+        let generated = chunked.get_value_at(1, 1, 1).unwrap();
+        chunked.set_value_at(1, 1, 1, generated + 10.0).unwrap();
+
+        chunked.get_value_at(9, 1, 1).unwrap();
+        assert!(!chunked.is_loaded(ChunkCoord::new(0, 0, 0)));
+        assert_eq!(chunked.get_value_at(1, 1, 1).unwrap(), generated);
+
+        // Asking for a save is what reports the missing directory
+        assert!(matches!(chunked.save_dirty(), Ok(())));
+        chunked.set_value_at(1, 1, 1, 0.0).unwrap();
+        assert!(matches!(chunked.save_dirty(), Err(NoiseMapError::NoChunkDirectory)));
+    }
+
+    #[test]
+    fn mismatched_chunk_files_are_rejected()
+    {
+        let directory = temp_directory("mismatch");
+        let mut chunked = ChunkedNoiseMap::new(generator(), 4, 4).unwrap();
+        chunked.directory = Some(directory.clone());
+
+        // Wrong size where chunk (0, 0, 0) is expected
+        NoiseMap3D::from_values(2, 4, 4).save(format!("{}/chunk_0_0_0.noise3d", directory)).unwrap();
+        // Right size, but sitting at chunk (0, 0, 0)'s origin instead of (1, 0, 0)'s
+        NoiseMap3D::from_values(4, 4, 4).save(format!("{}/chunk_1_0_0.noise3d", directory)).unwrap();
+
+        let wrong_size = chunked.get_value_at(3, 3, 3);
+        let wrong_origin = chunked.load_chunk(ChunkCoord::new(1, 0, 0)).map(|_| ());
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(matches!(wrong_size, Err(NoiseMapError::ChunkMismatch { dimensions: [2, 4, 4], .. })), "{:?}", wrong_size);
+        assert!(matches!(wrong_origin, Err(NoiseMapError::ChunkMismatch { origin: [0.0, 0.0, 0.0], expected_origin: [4.0, 0.0, 0.0], .. })), "{:?}", wrong_origin);
+    }
+}
@@ -0,0 +1,252 @@
+use bevy::prelude::*;
+
+use crate::noise::NoiseMap2D;
+
+//-------------------------------------------------------------------Defining Erosion Settings-------------------------------------------------------------------//
+#[derive(Clone, Debug)]
+pub struct ErosionSettings
+{
+    pub droplets: usize,
+    // Steps a droplet takes before it is dropped, whatever sediment it still carries stays with it
+    pub max_lifetime: usize,
+    // 0 follows the slope exactly, 1 keeps going in the direction the droplet was already moving
+    pub inertia: f32,
+    // How much sediment a droplet can carry per unit of speed, water and downhill slope
+    pub capacity: f32,
+    // Keeps droplets on almost flat ground able to carry a little sediment
+    pub min_capacity: f32,
+    // Fraction of the excess sediment dropped, and of the spare capacity picked up, each step
+    pub deposition: f32,
+    pub erosion: f32,
+    // Fraction of the water lost each step
+    pub evaporation: f32,
+    pub gravity: f32,
+    pub seed: u64,
+    // Fill the sediment / flow maps of the returned ErosionMaps
+    pub record_sediment: bool,
+    pub record_flow: bool,
+}
+impl Default for ErosionSettings
+{
+    fn default() -> ErosionSettings
+    {
+        ErosionSettings {
+            droplets: 50_000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+            seed: 0,
+            record_sediment: false,
+            record_flow: false,
+        }
+    }
+}
+
+// Same size as the eroded map.
+// sediment holds the net height each cell gained from deposits, negative where material was carried away.
+// flow holds the water that passed through each cell, summed over every droplet step
+pub struct ErosionMaps
+{
+    pub sediment: Option<NoiseMap2D>,
+    pub flow: Option<NoiseMap2D>,
+}
+
+
+//-------------------------------------------------------------------Defining Hydraulic Erosion-------------------------------------------------------------------//
+// Small splitmix64 generator so the same seed always spawns the same droplets on every platform
+struct SplitMix64
+{
+    state: u64,
+}
+impl SplitMix64
+{
+    fn next_u64(&mut self) -> u64
+    {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    // Uniform in 0..1
+    fn next_f32(&mut self) -> f32
+    {
+        return (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+    }
+}
+
+// Height and gradient at a point between cells, bilinearly interpolated from the four surrounding cells
+fn height_and_gradient(map: &NoiseMap2D, position: Vec2) -> (f32, Vec2)
+{
+    let (cell_x, cell_y) = (position.x as usize, position.y as usize);
+    let (offset_x, offset_y) = (position.x.fract(), position.y.fract());
+    let index = cell_y * map.width + cell_x;
+
+    let top_left = map.buffer[index];
+    let top_right = map.buffer[index + 1];
+    let bottom_left = map.buffer[index + map.width];
+    let bottom_right = map.buffer[index + map.width + 1];
+
+    let gradient = Vec2::new(
+        (top_right - top_left) * (1.0 - offset_y) + (bottom_right - bottom_left) * offset_y,
+        (bottom_left - top_left) * (1.0 - offset_x) + (bottom_right - top_right) * offset_x,
+    );
+    let height = top_left * (1.0 - offset_x) * (1.0 - offset_y)
+        + top_right * offset_x * (1.0 - offset_y)
+        + bottom_left * (1.0 - offset_x) * offset_y
+        + bottom_right * offset_x * offset_y;
+    return (height, gradient);
+}
+
+// Adds amount to the four cells around position, split by the same bilinear weights used to read them
+fn spread(buffer: &mut [f32], width: usize, position: Vec2, amount: f32)
+{
+    let (cell_x, cell_y) = (position.x as usize, position.y as usize);
+    let (offset_x, offset_y) = (position.x.fract(), position.y.fract());
+    let index = cell_y * width + cell_x;
+
+    buffer[index] += amount * (1.0 - offset_x) * (1.0 - offset_y);
+    buffer[index + 1] += amount * offset_x * (1.0 - offset_y);
+    buffer[index + width] += amount * (1.0 - offset_x) * offset_y;
+    buffer[index + width + 1] += amount * offset_x * offset_y;
+}
+
+// Particle based hydraulic erosion, each droplet rolls downhill picking up sediment while it speeds up and dropping it where it slows down or fills a pit.
+// Droplets run one after another so the result only depends on the map and the settings
+pub fn erode(map: &mut NoiseMap2D, settings: &ErosionSettings) -> ErosionMaps
+{
+    let (width, height) = (map.width, map.height);
+    let mut sediment_map = if settings.record_sediment { Some(NoiseMap2D::from_values(width, height)) } else { None };
+    let mut flow_map = if settings.record_flow { Some(NoiseMap2D::from_values(width, height)) } else { None };
+    if width < 2 || height < 2 {
+        return ErosionMaps { sediment: sediment_map, flow: flow_map };
+    }
+
+    let mut random = SplitMix64 { state: settings.seed };
+    // Droplets stay at least one cell inside the right and bottom edges so all four interpolation cells exist
+    let limit = Vec2::new((width - 1) as f32, (height - 1) as f32);
+
+    for _ in 0..settings.droplets {
+        let mut position = Vec2::new(random.next_f32() * limit.x, random.next_f32() * limit.y);
+        // Rounding can land a spawn exactly on the limit on large maps
+        if position.x >= limit.x || position.y >= limit.y {
+            continue;
+        }
+        let mut direction = Vec2::ZERO;
+        let mut speed: f32 = 1.0;
+        let mut water: f32 = 1.0;
+        let mut sediment: f32 = 0.0;
+
+        for _ in 0..settings.max_lifetime {
+            let (old_height, gradient) = height_and_gradient(map, position);
+            if let Some(flow) = flow_map.as_mut() {
+                spread(&mut flow.buffer, width, position, water);
+            }
+
+            direction = direction * settings.inertia - gradient * (1.0 - settings.inertia);
+            // Stuck on perfectly flat ground
+            if direction.length_squared() == 0.0 {
+                break;
+            }
+            direction = direction.normalize();
+
+            let new_position = position + direction;
+            if new_position.x < 0.0 || new_position.y < 0.0 || new_position.x >= limit.x || new_position.y >= limit.y {
+                break;
+            }
+
+            let (new_height, _) = height_and_gradient(map, new_position);
+            let height_change = new_height - old_height;
+            let capacity = (-height_change * speed * water * settings.capacity).max(settings.min_capacity);
+
+            // Moving uphill fills the pit behind the droplet as far as it can, carrying too much drops part of the excess
+            let deposit = if height_change > 0.0 {
+                height_change.min(sediment)
+            } else if sediment > capacity {
+                (sediment - capacity) * settings.deposition
+            } else {
+                // Never dig deeper than the drop to the next position, or the droplet would carve a hole it then has to climb out of
+                -((capacity - sediment) * settings.erosion).min(-height_change)
+            };
+            sediment -= deposit;
+            spread(&mut map.buffer, width, position, deposit);
+            if let Some(sediment_map) = sediment_map.as_mut() {
+                spread(&mut sediment_map.buffer, width, position, deposit);
+            }
+
+            speed = (speed * speed - height_change * settings.gravity).max(0.0).sqrt();
+            water *= 1.0 - settings.evaporation;
+            position = new_position;
+        }
+    }
+
+    return ErosionMaps { sediment: sediment_map, flow: flow_map };
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::noise::{FractalMode, NoiseGenerator2D, NoiseValues};
+    use libnoise::prelude::*;
+
+    fn terrain() -> NoiseMap2D
+    {
+        let values = NoiseValues { octaves: 4, scale: 0.04, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+        let mut map = NoiseMap2D::from_values(64, 48);
+        map.populate_with(&NoiseGenerator2D { source: Perlin::<2>::new(21), values });
+        map.remap(|value| value * 20.0);
+        return map;
+    }
+
+    fn settings(seed: u64) -> ErosionSettings
+    {
+        return ErosionSettings { droplets: 3000, seed, record_sediment: true, record_flow: true, ..Default::default() };
+    }
+
+    fn run(seed: u64) -> (NoiseMap2D, ErosionMaps)
+    {
+        let mut map = terrain();
+        let maps = map.erode(&settings(seed));
+        return (map, maps);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_result()
+    {
+        let (first, first_maps) = run(5);
+        let (second, second_maps) = run(5);
+        assert_eq!(first.buffer, second.buffer);
+        assert_eq!(first_maps.sediment.unwrap().buffer, second_maps.sediment.unwrap().buffer);
+        assert_eq!(first_maps.flow.unwrap().buffer, second_maps.flow.unwrap().buffer);
+
+        // Something has to have moved for the comparison to mean anything, and another seed moves it differently
+        assert_ne!(first.buffer, terrain().buffer);
+        let (other, _) = run(6);
+        assert_ne!(first.buffer, other.buffer);
+    }
+
+    #[test]
+    fn erosion_never_adds_material()
+    {
+        let total = |map: &NoiseMap2D| map.buffer.iter().map(|&value| value as f64).sum::<f64>();
+        let before = total(&terrain());
+        let (after, maps) = run(9);
+        let after = total(&after);
+        // Droplets only move material around or carry it off the map, up to f32 rounding of each deposit
+        assert!(after <= before + 1e-3, "total height rose from {} to {}", before, after);
+        let sediment: f64 = maps.sediment.unwrap().buffer.iter().map(|&value| value as f64).sum();
+        assert!((after - before - sediment).abs() < 1e-2, "sediment map {} against a change of {}", sediment, after - before);
+
+        // Settings that leave the maps off don't record them
+        let maps = terrain().erode(&ErosionSettings { droplets: 10, ..Default::default() });
+        assert!(maps.sediment.is_none() && maps.flow.is_none());
+    }
+}
@@ -1,10 +1,22 @@
 //use statmenets
 use bevy::{prelude::*, render::render_resource::Buffer, transform::commands, asset::LoadState, time};
-use libnoise::*;
+use mesh::*;
 use noise::*;
+use noise_config::*;
+use noise_file::*;
+use noise_view::*;
 
 //Module definitions
+mod chunked;
+mod erosion;
+mod marching_cubes;
+mod mesh;
+mod mesh_export;
 mod noise;
+mod noise_config;
+mod noise_file;
+mod noise_view;
+mod normal_map;
 
 // Consts
 const NOISE_MAP_WIDTH: usize = 100;
@@ -22,18 +34,25 @@ struct FlowState
 
 fn main() {
     
-    let _ = App::new()
-    .add_plugins(DefaultPlugins)
-    .add_systems(Startup, setup_from_load)
-    .add_systems(Update, update_sprite)
-    .run();
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins);
+
+    //--terrain shows the default preset as a 3D terrain mesh instead of playing back the z slices
+    if std::env::args().any(|argument| argument == "--terrain") {
+        app.add_systems(Startup, setup_terrain);
+    } else {
+        app.add_systems(Startup, setup_from_load)
+        .add_systems(Update, update_sprite);
+    }
+    app.run();
 }
 
 fn setup_from_load(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlas>>)
 {
     commands.spawn(Camera2dBundle::default());
 
-    let noise_map = NoiseMap3D::load(String::from("test.noise3d")).unwrap();   
+    //slices are read from the file as they are shown so playback starts without loading the whole volume
+    let mut noise_reader = NoiseMap3DReader::open("test.noise3d").unwrap();
     let noise_flow: FlowState = FlowState
      {
          speed: 1.0,
@@ -42,7 +61,7 @@ fn setup_from_load(mut commands: Commands, asset_server: Res<AssetServer>, mut t
          change_interval: 0.033,
      };
 
-    let img = noise_map.get_slice_z(75).to_image();
+    let img = noise_reader.read_slice_z(75).unwrap().to_image();
     // Create texture from the image data
     let image_handle = asset_server.add(img);   
 
@@ -54,7 +73,7 @@ fn setup_from_load(mut commands: Commands, asset_server: Res<AssetServer>, mut t
         ..Default::default()
     });
       
-    commands.insert_resource(noise_map);    
+    commands.insert_resource(noise_reader);    
     commands.insert_resource(noise_flow);
 
 }
@@ -63,26 +82,20 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atl
 {
     commands.spawn(Camera2dBundle::default());
 
-    let perlin_source = Perlin::new(3127812567548);
-    //generating a NoiseValues struct for the noise generator
-    let perlin_values = NoiseValues
-    {
-        octaves: 2,
-        scale: 0.075,
-        persistence: 0.5,
-        lacunarity: 2.0,
-    };
-    //generating the noise generator obj
-    let noise_gen = NoiseGenerator3D
-    {
-        source: perlin_source,
-        values: perlin_values,
-    };    
+    //loading the generator description from a preset so it can be changed without recompiling
+    let noise_config = GeneratorConfig::load("presets/default.ron").unwrap();
 
     let mut noise_map: NoiseMap3D = NoiseMap3D::from_values(NOISE_MAP_WIDTH, NOISE_MAP_HEIGHT, NOISE_MAP_DEPTH);
-    noise_map.populate_from_perlin(&noise_gen);
+    //single source presets are generated looping over z so playback never jumps when it wraps around
+    match &noise_config {
+        GeneratorConfig::Source(source) => noise_map.populate_looping(&*source.build_source_4d(), &source.values),
+        _ => noise_map.populate_with(&*noise_config.build_3d()),
+    }
+    noise_map.metadata = Some(NoiseMetadata::from_generator(&noise_config));
 
-    let _ = noise_map.save(String::from("test.noise3d"));
+    let save_options = SaveOptions { compression: Compression::Deflate { level: 6 }, shuffle: true, data_type: DataType::F32 };
+    noise_map.save_with(String::from("test.noise3d"), &save_options).unwrap();
+    let noise_reader = NoiseMap3DReader::open("test.noise3d").unwrap();
 
    let noise_flow: FlowState = FlowState
     {
@@ -106,16 +119,47 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atl
         ..Default::default()
     });
       
-    commands.insert_resource(noise_map);    
+    commands.insert_resource(noise_reader);    
     commands.insert_resource(noise_flow);
 
 }
 
+fn setup_terrain(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>)
+{
+    let noise_config = GeneratorConfig::load("presets/default.ron").unwrap();
+
+    let mut noise_map = NoiseMap2D::from_values(NOISE_MAP_WIDTH, NOISE_MAP_HEIGHT);
+    noise_map.populate_with(&*noise_config.build_2d());
+
+    let terrain_settings = TerrainSettings
+    {
+        spacing: 1.0,
+        height_scale: 20.0,
+        skirt_depth: 2.0,
+        colour_stops: vec![(-0.5, [0.1, 0.2, 0.6, 1.0]), (0.0, [0.2, 0.6, 0.2, 1.0]), (0.5, [0.9, 0.9, 0.9, 1.0])],
+    };
+
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(noise_map.to_terrain_mesh(&terrain_settings)),
+        material: materials.add(StandardMaterial { base_color: Color::WHITE, perceptual_roughness: 0.9, ..Default::default() }),
+        transform: Transform::from_xyz(-(NOISE_MAP_WIDTH as f32) / 2.0, 0.0, -(NOISE_MAP_HEIGHT as f32) / 2.0),
+        ..Default::default()
+    });
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(50.0, 100.0, 50.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..Default::default()
+    });
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0.0, 80.0, 120.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..Default::default()
+    });
+}
+
 fn update_sprite(
     mut commands: Commands,
     mut query: Query<(&mut Handle<Image>, Entity)>,
     //mut sprites_query: Query<&mut Sprite>,
-    noise_map: Res<NoiseMap3D>,
+    mut noise_reader: ResMut<NoiseMap3DReader>,
     asset_server: Res<AssetServer>,
     mut noise_flow: ResMut<FlowState>,
     time: Res<Time>,
@@ -129,12 +173,18 @@ fn update_sprite(
         noise_flow.time_since_change = 0.0;
     }
 
-    noise_flow.z_position += noise_flow.speed;
+    noise_flow.z_position = (noise_flow.z_position + noise_flow.speed) % noise_reader.depth.max(1) as f32;
     
     println!("Moving z slice to: {}", noise_flow.z_position as i32);
 
     // Get the image and create a new texture handle
-    let img = noise_map.get_slice_z(noise_flow.z_position as u32).to_image();
+    let img = match noise_reader.read_slice_z(noise_flow.z_position as usize) {
+        Ok(slice) => slice.to_image(),
+        Err(error) => {
+            error!("Failed to read z slice: {}", error);
+            return;
+        }
+    };
     let new_image_handle = asset_server.add(img);
     
     for(handle_image, entity) in &query
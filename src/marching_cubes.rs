@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use crate::mesh::MeshData;
+use crate::noise::NoiseMap3D;
+
+//-------------------------------------------------------------------Defining Marching Cubes-------------------------------------------------------------------//
+// Cube corners and edges are numbered like Paul Bourke's "Polygonising a scalar field":
+// corner 0 is (0, 0, 0), 1-3 go round the z = 0 face, 4-7 repeat them at z = 1,
+// edges 0-3 join the z = 0 corners, 4-7 the z = 1 corners and 8-11 run along z.
+const CORNER_OFFSETS: [[usize; 3]; 8] = [
+    [0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0],
+    [0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1],
+];
+
+const EDGE_CORNERS: [[usize; 2]; 12] = [
+    [0, 1], [1, 2], [2, 3], [3, 0],
+    [4, 5], [5, 6], [6, 7], [7, 4],
+    [0, 4], [1, 5], [2, 6], [3, 7],
+];
+
+// Cells whose value is above the threshold count as inside
+pub fn extract_isosurface(map: &NoiseMap3D, threshold: f32) -> MeshData
+{
+    let mut mesh = MeshData::new();
+    if map.width < 2 || map.height < 2 || map.depth < 2 {
+        return mesh;
+    }
+
+    // Neighbouring cubes share the vertex on their common edge, keyed by the edge's lower corner and axis
+    let mut edge_vertices: HashMap<([usize; 3], usize), u32> = HashMap::new();
+
+    for z in 0..map.depth - 1 {
+        for y in 0..map.height - 1 {
+            for x in 0..map.width - 1 {
+                let corners = CORNER_OFFSETS.map(|offset| [x + offset[0], y + offset[1], z + offset[2]]);
+                let values = corners.map(|corner| map.get_value_at(corner[0] as u32, corner[1] as u32, corner[2] as u32));
+
+                let mut case = 0;
+                for (index, &value) in values.iter().enumerate() {
+                    if value > threshold {
+                        case |= 1 << index;
+                    }
+                }
+
+                for &edge in TRI_TABLE[case].iter().take_while(|&&edge| edge >= 0) {
+                    let [a, b] = EDGE_CORNERS[edge as usize];
+                    let (low, high) = if corners[a] < corners[b] { (a, b) } else { (b, a) };
+                    let axis = (0..3).find(|&axis| corners[low][axis] != corners[high][axis]).unwrap();
+
+                    let index = *edge_vertices.entry((corners[low], axis)).or_insert_with(|| {
+                        let t = crossing(values[low], values[high], threshold);
+                        let position = lerp3(map.world_position(corners[low][0], corners[low][1], corners[low][2]), map.world_position(corners[high][0], corners[high][1], corners[high][2]), t);
+                        let normal = lerp3(outward_normal(map, corners[low]), outward_normal(map, corners[high]), t);
+
+                        mesh.positions.push(position.map(|value| value as f32));
+                        mesh.normals.push(normalise3(normal));
+                        (mesh.positions.len() - 1) as u32
+                    });
+                    mesh.indices.push(index);
+                }
+            }
+        }
+    }
+
+    return mesh;
+}
+
+// Where between two corner values the threshold is crossed, 0 at the first and 1 at the second
+fn crossing(from: f32, to: f32, threshold: f32) -> f64
+{
+    if (to - from).abs() <= f32::EPSILON {
+        return 0.5;
+    }
+    return (((threshold - from) / (to - from)) as f64).clamp(0.0, 1.0);
+}
+
+// Points from high values to low ones, out of the surface, using central differences (one sided at the borders)
+fn outward_normal(map: &NoiseMap3D, corner: [usize; 3]) -> [f64; 3]
+{
+    let dimensions = [map.width, map.height, map.depth];
+    let mut normal = [0.0; 3];
+    for axis in 0..3 {
+        let mut before = corner;
+        let mut after = corner;
+        before[axis] = corner[axis].saturating_sub(1);
+        after[axis] = (corner[axis] + 1).min(dimensions[axis] - 1);
+
+        let value_before = map.get_value_at(before[0] as u32, before[1] as u32, before[2] as u32);
+        let value_after = map.get_value_at(after[0] as u32, after[1] as u32, after[2] as u32);
+        normal[axis] = -(value_after - value_before) as f64 / ((after[axis] - before[axis]) as f64 * map.cell_size);
+    }
+    return normal;
+}
+
+fn lerp3(a: [f64; 3], b: [f64; 3], t: f64) -> [f64; 3]
+{
+    return [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t];
+}
+
+fn normalise3(vector: [f64; 3]) -> [f32; 3]
+{
+    let length = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if length == 0.0 {
+        return [0.0, 1.0, 0.0];
+    }
+    return [(vector[0] / length) as f32, (vector[1] / length) as f32, (vector[2] / length) as f32];
+}
+
+// Edges cut by the surface for every inside / outside combination of the 8 corners (bit n set = corner n inside),
+// as up to 5 triangles wound counter clockwise seen from outside, -1 terminated.
+// Faces with two diagonally opposite inside corners always keep those corners apart, so neighbouring cubes agree and the surface has no holes.
+// No triangle has all three corners, and no inner diagonal both ends, on one face of the cube, so neighbouring cubes never both emit an edge or triangle lying in the face they share
+const TRI_TABLE: [[i8; 16]; 256] = [
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 8, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 10, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 2, 9, 10, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 3, 8, 10, 8, 9, 10, -1, -1, -1, -1, -1, -1, -1],
+    [2, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 11, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 2, 9, 2, 11, 9, 11, 8, 9, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 3, 10, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 10, 8, 10, 11, 8, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 3, 9, 10, 3, 10, 11, 3, -1, -1, -1, -1, -1, -1, -1],
+    [8, 9, 11, 9, 10, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 7, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 7, 9, 7, 4, 9, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 7, 4, 1, 10, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 2, 9, 10, 2, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 3, 7, 10, 7, 4, 10, 4, 9, 10, -1, -1, -1, -1],
+    [2, 11, 3, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 4, 2, 11, 4, 11, 7, 4, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 11, 3, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [1, 2, 9, 2, 11, 9, 11, 7, 9, 7, 4, 9, -1, -1, -1, -1],
+    [1, 10, 3, 10, 11, 3, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 4, 1, 10, 4, 10, 11, 4, 11, 7, 4, -1, -1, -1, -1],
+    [0, 9, 3, 9, 10, 3, 10, 11, 3, 4, 8, 7, -1, -1, -1, -1],
+    [4, 9, 7, 9, 10, 7, 10, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [4, 5, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 1, 4, 5, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 5, 3, 8, 5, 8, 4, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 10, 2, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 2, 4, 5, 2, 5, 10, 2, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 3, 8, 10, 8, 4, 10, 4, 5, 10, -1, -1, -1, -1],
+    [2, 11, 3, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 11, 8, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 1, 4, 5, 1, 2, 11, 3, -1, -1, -1, -1, -1, -1, -1],
+    [1, 2, 5, 2, 11, 5, 11, 8, 5, 8, 4, 5, -1, -1, -1, -1],
+    [1, 10, 3, 10, 11, 3, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 10, 8, 10, 11, 8, 4, 5, 9, -1, -1, -1, -1],
+    [0, 4, 3, 4, 5, 3, 5, 10, 3, 10, 11, 3, -1, -1, -1, -1],
+    [4, 5, 8, 5, 10, 8, 10, 11, 8, -1, -1, -1, -1, -1, -1, -1],
+    [5, 9, 7, 9, 8, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 7, 9, 7, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 1, 8, 7, 1, 7, 5, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 5, 3, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, 5, 9, 7, 9, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 7, 9, 7, 5, 9, 1, 10, 2, -1, -1, -1, -1],
+    [0, 8, 2, 8, 7, 2, 7, 5, 2, 5, 10, 2, -1, -1, -1, -1],
+    [2, 3, 10, 3, 7, 10, 7, 5, 10, -1, -1, -1, -1, -1, -1, -1],
+    [2, 11, 3, 5, 9, 7, 9, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 9, 2, 11, 9, 11, 7, 9, 7, 5, 9, -1, -1, -1, -1],
+    [0, 8, 1, 8, 7, 1, 7, 5, 1, 2, 11, 3, -1, -1, -1, -1],
+    [1, 2, 5, 2, 11, 5, 11, 7, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 3, 10, 11, 3, 5, 9, 7, 9, 8, 7, -1, -1, -1, -1],
+    [0, 1, 11, 1, 10, 11, 0, 11, 9, 11, 7, 9, 7, 5, 9, -1],
+    [0, 8, 5, 8, 7, 5, 0, 5, 3, 5, 10, 3, 10, 11, 3, -1],
+    [5, 10, 7, 10, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 8, 9, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 2, 5, 6, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 5, 2, 5, 6, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 2, 9, 5, 2, 5, 6, 2, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 6, 3, 8, 6, 8, 9, 6, 9, 5, 6, -1, -1, -1, -1],
+    [2, 11, 3, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 11, 8, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 11, 3, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 2, 9, 2, 11, 9, 11, 8, 9, 5, 6, 10, -1, -1, -1, -1],
+    [1, 5, 3, 5, 6, 3, 6, 11, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 5, 8, 5, 6, 8, 6, 11, 8, -1, -1, -1, -1],
+    [0, 9, 3, 9, 5, 3, 5, 6, 3, 6, 11, 3, -1, -1, -1, -1],
+    [5, 6, 9, 6, 11, 9, 11, 8, 9, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 7, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 7, 4, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 4, 8, 7, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 7, 9, 7, 4, 9, 5, 6, 10, -1, -1, -1, -1],
+    [1, 5, 2, 5, 6, 2, 4, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 7, 4, 1, 5, 2, 5, 6, 2, -1, -1, -1, -1],
+    [0, 9, 2, 9, 5, 2, 5, 6, 2, 4, 8, 7, -1, -1, -1, -1],
+    [2, 3, 6, 3, 7, 9, 7, 4, 9, 3, 9, 6, 9, 5, 6, -1],
+    [2, 11, 3, 4, 8, 7, 5, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 4, 2, 11, 4, 11, 7, 4, 5, 6, 10, -1, -1, -1, -1],
+    [0, 9, 1, 2, 11, 3, 4, 8, 7, 5, 6, 10, -1, -1, -1, -1],
+    [1, 2, 9, 2, 11, 9, 11, 7, 9, 7, 4, 9, 5, 6, 10, -1],
+    [1, 5, 3, 5, 6, 3, 6, 11, 3, 4, 8, 7, -1, -1, -1, -1],
+    [0, 1, 4, 1, 5, 11, 5, 6, 11, 1, 11, 4, 11, 7, 4, -1],
+    [0, 9, 3, 9, 5, 3, 5, 6, 3, 6, 11, 3, 4, 8, 7, -1],
+    [4, 9, 7, 9, 5, 11, 5, 6, 11, 9, 11, 7, -1, -1, -1, -1],
+    [4, 6, 9, 6, 10, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 4, 6, 9, 6, 10, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 1, 4, 6, 1, 6, 10, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 10, 3, 8, 10, 8, 4, 10, 4, 6, 10, -1, -1, -1, -1],
+    [1, 9, 2, 9, 4, 2, 4, 6, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 9, 2, 9, 4, 2, 4, 6, 2, -1, -1, -1, -1],
+    [0, 4, 2, 4, 6, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 6, 3, 8, 6, 8, 4, 6, -1, -1, -1, -1, -1, -1, -1],
+    [2, 11, 3, 4, 6, 9, 6, 10, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 11, 8, 4, 6, 9, 6, 10, 9, -1, -1, -1, -1],
+    [0, 4, 1, 4, 6, 1, 6, 10, 1, 2, 11, 3, -1, -1, -1, -1],
+    [1, 2, 8, 2, 11, 8, 1, 8, 10, 8, 4, 10, 4, 6, 10, -1],
+    [1, 9, 3, 9, 4, 3, 4, 6, 3, 6, 11, 3, -1, -1, -1, -1],
+    [0, 1, 8, 1, 9, 6, 9, 4, 6, 1, 6, 8, 6, 11, 8, -1],
+    [0, 4, 3, 4, 6, 3, 6, 11, 3, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 8, 6, 11, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 10, 7, 10, 9, 7, 9, 8, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 7, 9, 7, 6, 9, 6, 10, 9, -1, -1, -1, -1],
+    [0, 8, 1, 8, 7, 1, 7, 6, 1, 6, 10, 1, -1, -1, -1, -1],
+    [1, 3, 10, 3, 7, 10, 7, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 9, 2, 9, 8, 2, 8, 7, 2, 7, 6, 2, -1, -1, -1, -1],
+    [0, 3, 9, 3, 7, 9, 7, 6, 9, 6, 2, 9, 2, 1, 9, -1],
+    [0, 8, 2, 8, 7, 2, 7, 6, 2, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 6, 3, 7, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 11, 3, 6, 10, 7, 10, 9, 7, 9, 8, 7, -1, -1, -1, -1],
+    [0, 2, 9, 2, 11, 9, 11, 7, 9, 7, 6, 9, 6, 10, 9, -1],
+    [0, 8, 1, 8, 7, 1, 7, 6, 1, 6, 10, 1, 2, 11, 3, -1],
+    [1, 2, 7, 2, 11, 7, 1, 7, 10, 7, 6, 10, -1, -1, -1, -1],
+    [1, 9, 3, 9, 8, 6, 8, 7, 6, 9, 6, 3, 6, 11, 3, -1],
+    [0, 1, 9, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 6, 8, 7, 6, 0, 6, 3, 6, 11, 3, -1, -1, -1, -1],
+    [6, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 8, 9, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 10, 2, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 2, 9, 10, 2, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 3, 8, 10, 8, 9, 10, 6, 7, 11, -1, -1, -1, -1],
+    [2, 6, 3, 6, 7, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 6, 8, 6, 7, 8, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 6, 3, 6, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [1, 2, 9, 2, 6, 9, 6, 7, 9, 7, 8, 9, -1, -1, -1, -1],
+    [1, 10, 3, 10, 6, 3, 6, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 10, 8, 10, 6, 8, 6, 7, 8, -1, -1, -1, -1],
+    [0, 9, 3, 9, 10, 3, 10, 6, 3, 6, 7, 3, -1, -1, -1, -1],
+    [6, 7, 10, 7, 8, 10, 8, 9, 10, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 6, 8, 11, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 11, 4, 11, 6, 4, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 4, 8, 6, 8, 11, 6, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 11, 9, 11, 6, 9, 6, 4, 9, -1, -1, -1, -1],
+    [1, 10, 2, 4, 8, 6, 8, 11, 6, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 11, 4, 11, 6, 4, 1, 10, 2, -1, -1, -1, -1],
+    [0, 9, 2, 9, 10, 2, 4, 8, 6, 8, 11, 6, -1, -1, -1, -1],
+    [2, 3, 10, 3, 11, 4, 11, 6, 4, 3, 4, 10, 4, 9, 10, -1],
+    [2, 6, 3, 6, 4, 3, 4, 8, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 4, 2, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 6, 3, 6, 4, 3, 4, 8, 3, -1, -1, -1, -1],
+    [1, 2, 9, 2, 6, 9, 6, 4, 9, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 3, 10, 6, 3, 6, 4, 3, 4, 8, 3, -1, -1, -1, -1],
+    [0, 1, 4, 1, 10, 4, 10, 6, 4, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 3, 9, 10, 3, 10, 6, 3, 6, 4, 3, 4, 8, 3, -1],
+    [4, 9, 6, 9, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 5, 9, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 4, 5, 9, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 1, 4, 5, 1, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 5, 3, 8, 5, 8, 4, 5, 6, 7, 11, -1, -1, -1, -1],
+    [1, 10, 2, 4, 5, 9, 6, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 10, 2, 4, 5, 9, 6, 7, 11, -1, -1, -1, -1],
+    [0, 4, 2, 4, 5, 2, 5, 10, 2, 6, 7, 11, -1, -1, -1, -1],
+    [2, 3, 10, 3, 8, 10, 8, 4, 10, 4, 5, 10, 6, 7, 11, -1],
+    [2, 6, 3, 6, 7, 3, 4, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 6, 8, 6, 7, 8, 4, 5, 9, -1, -1, -1, -1],
+    [0, 4, 1, 4, 5, 1, 2, 6, 3, 6, 7, 3, -1, -1, -1, -1],
+    [1, 2, 5, 2, 6, 8, 6, 7, 8, 2, 8, 5, 8, 4, 5, -1],
+    [1, 10, 3, 10, 6, 3, 6, 7, 3, 4, 5, 9, -1, -1, -1, -1],
+    [0, 1, 8, 1, 10, 8, 10, 6, 8, 6, 7, 8, 4, 5, 9, -1],
+    [0, 4, 3, 4, 5, 3, 5, 10, 3, 10, 6, 3, 6, 7, 3, -1],
+    [4, 5, 8, 5, 10, 8, 10, 6, 8, 6, 7, 8, -1, -1, -1, -1],
+    [5, 9, 6, 9, 8, 6, 8, 11, 6, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 11, 9, 11, 6, 9, 6, 5, 9, -1, -1, -1, -1],
+    [0, 8, 1, 8, 11, 1, 11, 6, 1, 6, 5, 1, -1, -1, -1, -1],
+    [1, 3, 5, 3, 11, 5, 11, 6, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 2, 5, 9, 6, 9, 8, 6, 8, 11, 6, -1, -1, -1, -1],
+    [0, 3, 9, 3, 11, 9, 11, 6, 9, 6, 5, 9, 1, 10, 2, -1],
+    [0, 8, 2, 8, 11, 5, 11, 6, 5, 8, 5, 2, 5, 10, 2, -1],
+    [2, 3, 10, 3, 11, 5, 11, 6, 5, 3, 5, 10, -1, -1, -1, -1],
+    [2, 6, 3, 6, 5, 3, 5, 9, 3, 9, 8, 3, -1, -1, -1, -1],
+    [0, 2, 9, 2, 6, 9, 6, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 1, 8, 3, 6, 3, 2, 6, 8, 6, 1, 6, 5, 1, -1],
+    [1, 2, 5, 2, 6, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 3, 10, 6, 3, 6, 5, 3, 5, 9, 3, 9, 8, 3, -1],
+    [0, 1, 6, 1, 10, 6, 0, 6, 9, 6, 5, 9, -1, -1, -1, -1],
+    [0, 8, 3, 5, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 7, 10, 7, 11, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 5, 7, 10, 7, 11, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 5, 7, 10, 7, 11, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 9, 3, 8, 9, 5, 7, 10, 7, 11, 10, -1, -1, -1, -1],
+    [1, 5, 2, 5, 7, 2, 7, 11, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 1, 5, 2, 5, 7, 2, 7, 11, 2, -1, -1, -1, -1],
+    [0, 9, 2, 9, 5, 2, 5, 7, 2, 7, 11, 2, -1, -1, -1, -1],
+    [2, 3, 9, 3, 8, 9, 2, 9, 11, 9, 5, 11, 5, 7, 11, -1],
+    [2, 10, 3, 10, 5, 3, 5, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 8, 2, 10, 8, 10, 5, 8, 5, 7, 8, -1, -1, -1, -1],
+    [0, 9, 1, 2, 10, 3, 10, 5, 3, 5, 7, 3, -1, -1, -1, -1],
+    [1, 2, 9, 2, 10, 7, 10, 5, 7, 2, 7, 9, 7, 8, 9, -1],
+    [1, 5, 3, 5, 7, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 5, 8, 5, 7, 8, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 3, 9, 5, 3, 5, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [5, 7, 9, 7, 8, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 5, 8, 11, 5, 11, 10, 5, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 4, 3, 11, 4, 11, 10, 4, 10, 5, 4, -1, -1, -1, -1],
+    [0, 9, 1, 4, 8, 5, 8, 11, 5, 11, 10, 5, -1, -1, -1, -1],
+    [1, 3, 9, 3, 11, 9, 11, 10, 4, 10, 5, 4, 11, 4, 9, -1],
+    [1, 5, 2, 5, 4, 2, 4, 8, 2, 8, 11, 2, -1, -1, -1, -1],
+    [0, 3, 4, 3, 11, 4, 11, 2, 4, 2, 1, 4, 1, 5, 4, -1],
+    [0, 9, 2, 9, 5, 2, 5, 4, 2, 4, 8, 2, 8, 11, 2, -1],
+    [2, 3, 11, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 10, 3, 10, 5, 3, 5, 4, 3, 4, 8, 3, -1, -1, -1, -1],
+    [0, 2, 4, 2, 10, 4, 10, 5, 4, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 1, 2, 10, 3, 10, 5, 3, 5, 4, 3, 4, 8, 3, -1],
+    [1, 2, 9, 2, 10, 4, 10, 5, 4, 2, 4, 9, -1, -1, -1, -1],
+    [1, 5, 3, 5, 4, 3, 4, 8, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 4, 1, 5, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 3, 9, 5, 3, 5, 4, 3, 4, 8, 3, -1, -1, -1, -1],
+    [4, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 7, 9, 7, 11, 9, 11, 10, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 8, 4, 7, 9, 7, 11, 9, 11, 10, 9, -1, -1, -1, -1],
+    [0, 4, 1, 4, 7, 1, 7, 11, 1, 11, 10, 1, -1, -1, -1, -1],
+    [1, 3, 10, 3, 8, 10, 8, 4, 10, 4, 7, 10, 7, 11, 10, -1],
+    [1, 9, 2, 9, 4, 2, 4, 7, 2, 7, 11, 2, -1, -1, -1, -1],
+    [0, 3, 8, 1, 9, 2, 9, 4, 2, 4, 7, 2, 7, 11, 2, -1],
+    [0, 4, 2, 4, 7, 2, 7, 11, 2, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 4, 3, 8, 4, 2, 4, 11, 4, 7, 11, -1, -1, -1, -1],
+    [2, 10, 3, 10, 9, 3, 9, 4, 3, 4, 7, 3, -1, -1, -1, -1],
+    [0, 2, 8, 2, 10, 8, 10, 9, 7, 9, 4, 7, 10, 7, 8, -1],
+    [0, 4, 1, 4, 7, 1, 7, 3, 10, 3, 2, 10, 7, 10, 1, -1],
+    [1, 2, 10, 4, 7, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 9, 3, 9, 4, 3, 4, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 8, 1, 9, 7, 9, 4, 7, 1, 7, 8, -1, -1, -1, -1],
+    [0, 4, 3, 4, 7, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 7, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 11, 9, 11, 10, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 11, 9, 11, 10, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 1, 8, 11, 1, 11, 10, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 10, 3, 11, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 9, 2, 9, 8, 2, 8, 11, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 3, 9, 3, 11, 9, 11, 2, 9, 2, 1, 9, -1, -1, -1, -1],
+    [0, 8, 2, 8, 11, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 10, 3, 10, 9, 3, 9, 8, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 9, 2, 10, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 1, 8, 3, 10, 3, 2, 10, 8, 10, 1, -1, -1, -1, -1],
+    [1, 2, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 9, 3, 9, 8, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+];
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Counts the triangles using each edge, a closed manifold surface uses every edge exactly twice, once in each direction
+    fn assert_closed_manifold(mesh: &MeshData)
+    {
+        assert!(mesh.triangle_count() > 0);
+        let mut undirected: HashMap<(u32, u32), usize> = HashMap::new();
+        let mut directed: HashMap<(u32, u32), usize> = HashMap::new();
+        for triangle in mesh.indices.chunks_exact(3) {
+            for corner in 0..3 {
+                let (from, to) = (triangle[corner], triangle[(corner + 1) % 3]);
+                *undirected.entry((from.min(to), from.max(to))).or_default() += 1;
+                *directed.entry((from, to)).or_default() += 1;
+            }
+        }
+        assert!(undirected.values().all(|&count| count == 2), "{} edges not shared by exactly 2 triangles", undirected.values().filter(|&&count| count != 2).count());
+        assert!(directed.values().all(|&count| count == 1), "triangles are not wound consistently");
+    }
+
+    // Every cell on the border is outside, so whatever the inside looks like the surface closes
+    fn closed_map<F>(size: usize, value: F) -> NoiseMap3D
+    where
+        F: Fn(usize, usize, usize) -> f32,
+    {
+        let mut map = NoiseMap3D::from_values(size, size, size);
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    let border = [x, y, z].iter().any(|&axis| axis == 0 || axis == size - 1);
+                    map.set_value_at(x as u32, y as u32, z as u32, if border { -1.0 } else { value(x, y, z) });
+                }
+            }
+        }
+        return map;
+    }
+
+    #[test]
+    fn sphere_is_closed()
+    {
+        let map = closed_map(16, |x, y, z| 5.0 - ((x as f32 - 7.5).powi(2) + (y as f32 - 7.5).powi(2) + (z as f32 - 7.5).powi(2)).sqrt());
+        assert_closed_manifold(&map.extract_isosurface(0.0));
+    }
+
+    // Random values hit every ambiguous face configuration, including the ones where one loop crosses a face twice
+    #[test]
+    fn random_field_is_closed()
+    {
+        for seed in 0..20u64 {
+            let map = closed_map(12, |x, y, z| {
+                // splitmix64 of the cell index, weaker mixes leave whole groups of cube cases out
+                let mut hash = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15).wrapping_add(((x * 12 + y) * 12 + z) as u64);
+                hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                hash ^= hash >> 31;
+                (hash >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            });
+            assert_closed_manifold(&map.extract_isosurface(0.0));
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::{prelude::*, render::{mesh::Indices, render_resource::PrimitiveTopology}};
+
+use crate::noise::NoiseMap2D;
+
+//-------------------------------------------------------------------Defining Mesh Data-------------------------------------------------------------------//
+// Plain indexed triangle list, counter clockwise when seen from the side the normals point to.
+// uvs and colours are either empty or hold one entry per position
+#[derive(Clone, Debug, Default)]
+pub struct MeshData
+{
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colours: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+impl MeshData
+{
+    pub fn new() -> MeshData
+    {
+        return MeshData::default();
+    }
+
+    pub fn vertex_count(&self) -> usize
+    {
+        return self.positions.len();
+    }
+
+    pub fn triangle_count(&self) -> usize
+    {
+        return self.indices.len() / 3;
+    }
+
+    // (min, max) corners of the box around every position, both zero for an empty mesh
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3])
+    {
+        if self.positions.is_empty() {
+            return ([0.0; 3], [0.0; 3]);
+        }
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for position in &self.positions {
+            for (axis, &value) in position.iter().enumerate() {
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+        return (min, max);
+    }
+
+    pub fn to_bevy_mesh(&self) -> Mesh
+    {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone());
+        if !self.uvs.is_empty() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs.clone());
+        }
+        if !self.colours.is_empty() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colours.clone());
+        }
+        mesh.set_indices(Some(Indices::U32(self.indices.clone())));
+        return mesh;
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Terrain Mesh-------------------------------------------------------------------//
+#[derive(Clone, Debug)]
+pub struct TerrainSettings
+{
+    // Distance between neighbouring cells along x and z
+    pub spacing: f32,
+    pub height_scale: f32,
+    // How far below the border the skirt hangs to hide cracks between neighbouring tiles, 0 for no skirt
+    pub skirt_depth: f32,
+    // (map value, rgba) pairs in ascending value order, vertices are coloured by interpolating between them, empty for no colours
+    pub colour_stops: Vec<(f32, [f32; 4])>,
+}
+impl Default for TerrainSettings
+{
+    fn default() -> TerrainSettings
+    {
+        TerrainSettings { spacing: 1.0, height_scale: 1.0, skirt_depth: 0.0, colour_stops: Vec::new() }
+    }
+}
+
+// One vertex per cell laid out on the xz plane with the map value as height, y up, map x along x and map y along z
+pub fn build_terrain_mesh(map: &NoiseMap2D, settings: &TerrainSettings) -> MeshData
+{
+    let mut mesh = MeshData::new();
+    if map.width < 2 || map.height < 2 {
+        return mesh;
+    }
+    let (width, height) = (map.width, map.height);
+    let height_at = |x: usize, y: usize| map.get_value_at(x as u32, y as u32) * settings.height_scale;
+
+    for y in 0..height {
+        for x in 0..width {
+            mesh.positions.push([x as f32 * settings.spacing, height_at(x, y), y as f32 * settings.spacing]);
+
+            // Central differences of the scaled height, one sided along the border
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (back, front) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let slope_x = (height_at(right, y) - height_at(left, y)) / ((right - left) as f32 * settings.spacing);
+            let slope_z = (height_at(x, front) - height_at(x, back)) / ((front - back) as f32 * settings.spacing);
+            mesh.normals.push(Vec3::new(-slope_x, 1.0, -slope_z).normalize().to_array());
+
+            mesh.uvs.push([x as f32 / (width - 1) as f32, y as f32 / (height - 1) as f32]);
+            if !settings.colour_stops.is_empty() {
+                mesh.colours.push(colour_at(&settings.colour_stops, map.get_value_at(x as u32, y as u32)));
+            }
+        }
+    }
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let top_left = (y * width + x) as u32;
+            let top_right = top_left + 1;
+            let bottom_left = top_left + width as u32;
+            let bottom_right = bottom_left + 1;
+            mesh.indices.extend_from_slice(&[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+        }
+    }
+
+    if settings.skirt_depth > 0.0 {
+        add_skirt(&mut mesh, width, height, settings.skirt_depth);
+    }
+
+    return mesh;
+}
+
+// Copies every border vertex skirt_depth lower and joins the two rings with outward facing quads
+fn add_skirt(mesh: &mut MeshData, width: usize, height: usize, skirt_depth: f32)
+{
+    // Border walked so the outside is always on the same side: along z = 0, x = max, z = max, then x = 0
+    let mut border: Vec<usize> = Vec::with_capacity(2 * (width + height));
+    border.extend(0..width - 1);
+    border.extend((0..height - 1).map(|y| y * width + width - 1));
+    border.extend((1..width).rev().map(|x| (height - 1) * width + x));
+    border.extend((1..height).rev().map(|y| y * width));
+
+    let first_skirt = mesh.positions.len();
+    for &index in &border {
+        let [x, y, z] = mesh.positions[index];
+        mesh.positions.push([x, y - skirt_depth, z]);
+        mesh.normals.push(mesh.normals[index]);
+        mesh.uvs.push(mesh.uvs[index]);
+        if !mesh.colours.is_empty() {
+            mesh.colours.push(mesh.colours[index]);
+        }
+    }
+
+    for edge in 0..border.len() {
+        let next = (edge + 1) % border.len();
+        let (top, top_next) = (border[edge] as u32, border[next] as u32);
+        let (skirt, skirt_next) = ((first_skirt + edge) as u32, (first_skirt + next) as u32);
+        mesh.indices.extend_from_slice(&[top, top_next, skirt, top_next, skirt_next, skirt]);
+    }
+}
+
+// A NaN value has no place between the stops, it gets the lowest stop's colour like anything below the first stop
+fn colour_at(stops: &[(f32, [f32; 4])], value: f32) -> [f32; 4]
+{
+    if value.is_nan() {
+        return stops[0].1;
+    }
+    let upper = stops.iter().position(|&(stop, _)| stop >= value).unwrap_or(stops.len() - 1);
+    if upper == 0 || stops[upper].0 < value {
+        return stops[upper].1;
+    }
+
+    let (from, from_colour) = stops[upper - 1];
+    let (to, to_colour) = stops[upper];
+    let t = if to > from { (value - from) / (to - from) } else { 1.0 };
+    return std::array::from_fn(|channel| from_colour[channel] + (to_colour[channel] - from_colour[channel]) * t);
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn triangle_normal(mesh: &MeshData, triangle: &[u32]) -> Vec3
+    {
+        let [a, b, c] = [0, 1, 2].map(|corner| Vec3::from_array(mesh.positions[triangle[corner] as usize]));
+        return (b - a).cross(c - a);
+    }
+
+    #[test]
+    fn terrain_mesh_counts_and_winding()
+    {
+        let (width, height) = (7, 4);
+        let mut map = NoiseMap2D::from_values(width, height);
+        map.populate_with(&|point: [f64; 2]| (point[0] * 0.8).sin() * 0.5 + point[1] * 0.2);
+        let settings = TerrainSettings { spacing: 2.0, height_scale: 3.0, skirt_depth: 1.5, colour_stops: vec![(-1.0, [0.0; 4]), (1.0, [1.0; 4])] };
+        let mesh = build_terrain_mesh(&map, &settings);
+
+        let surface_triangles = 2 * (width - 1) * (height - 1);
+        let border = 2 * (width - 1) + 2 * (height - 1);
+        assert_eq!(mesh.vertex_count(), width * height + border);
+        assert_eq!(mesh.triangle_count(), surface_triangles + 2 * border);
+        assert_eq!((mesh.normals.len(), mesh.uvs.len(), mesh.colours.len()), (mesh.vertex_count(), mesh.vertex_count(), mesh.vertex_count()));
+        assert!(mesh.indices.iter().all(|&index| (index as usize) < mesh.vertex_count()));
+
+        // Counter clockwise seen from above means the surface faces up, and every skirt quad faces away from the middle of the map
+        let centre = Vec3::new((width - 1) as f32, 0.0, (height - 1) as f32);
+        for (index, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+            let normal = triangle_normal(&mesh, triangle);
+            if index < surface_triangles {
+                assert!(normal.y > 0.0, "surface triangle {} faces {:?}", index, normal);
+            } else {
+                let middle = triangle.iter().map(|&corner| Vec3::from_array(mesh.positions[corner as usize])).sum::<Vec3>() / 3.0;
+                let outward = (middle - centre) * Vec3::new(1.0, 0.0, 1.0);
+                assert!(normal.dot(outward) > 0.0, "skirt triangle {} faces {:?}", index, normal);
+            }
+        }
+        assert!(mesh.normals[..width * height].iter().all(|normal| normal[1] > 0.0));
+
+        let without_skirt = build_terrain_mesh(&map, &TerrainSettings { skirt_depth: 0.0, ..settings });
+        assert_eq!((without_skirt.vertex_count(), without_skirt.triangle_count()), (width * height, surface_triangles));
+    }
+
+    #[test]
+    fn colours_interpolate_between_stops()
+    {
+        let stops = [(-1.0, [0.0, 0.0, 1.0, 1.0]), (1.0, [1.0, 1.0, 1.0, 1.0])];
+        assert_eq!(colour_at(&stops, 0.0), [0.5, 0.5, 1.0, 1.0]);
+        assert_eq!(colour_at(&stops, -5.0), stops[0].1);
+        assert_eq!(colour_at(&stops, 5.0), stops[1].1);
+        assert_eq!(colour_at(&stops, f32::NAN), stops[0].1);
+    }
+}
@@ -0,0 +1,436 @@
+use std::{fmt, fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use serde_json::json;
+
+use crate::mesh::MeshData;
+
+//-------------------------------------------------------------------Defining Mesh Export-------------------------------------------------------------------//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StlFormat
+{
+    Binary,
+    Ascii,
+}
+
+// Wavefront OBJ with 1 based v / vt / vn indices, vertex colours are appended to the v lines as r g b when present
+pub fn write_obj(mesh: &MeshData, path: &str) -> Result<(), MeshExportError>
+{
+    check_mesh_extension(path, &["obj"])?;
+    let mut file = BufWriter::new(File::create(path)?);
+
+    writeln!(file, "# exported by {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+    for (index, position) in mesh.positions.iter().enumerate() {
+        match mesh.colours.get(index) {
+            Some(colour) => writeln!(file, "v {} {} {} {} {} {}", position[0], position[1], position[2], colour[0], colour[1], colour[2])?,
+            None => writeln!(file, "v {} {} {}", position[0], position[1], position[2])?,
+        }
+    }
+    for uv in &mesh.uvs {
+        writeln!(file, "vt {} {}", uv[0], uv[1])?;
+    }
+    for normal in &mesh.normals {
+        writeln!(file, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let corners: Vec<String> = triangle.iter().map(|&index| {
+            let index = index + 1;
+            match (mesh.uvs.is_empty(), mesh.normals.is_empty()) {
+                (false, false) => format!("{}/{}/{}", index, index, index),
+                (true, false) => format!("{}//{}", index, index),
+                (false, true) => format!("{}/{}", index, index),
+                (true, true) => format!("{}", index),
+            }
+        }).collect();
+        writeln!(file, "f {}", corners.join(" "))?;
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+// STL has no shared vertices or smooth normals, every triangle is written out on its own with its face normal
+pub fn write_stl(mesh: &MeshData, path: &str, format: StlFormat) -> Result<(), MeshExportError>
+{
+    check_mesh_extension(path, &["stl"])?;
+    let mut file = BufWriter::new(File::create(path)?);
+
+    let triangles: Vec<[[f32; 3]; 3]> = mesh.indices
+        .chunks_exact(3)
+        .map(|triangle| [mesh.positions[triangle[0] as usize], mesh.positions[triangle[1] as usize], mesh.positions[triangle[2] as usize]])
+        .collect();
+
+    match format {
+        StlFormat::Binary => {
+            let mut header = [0u8; 80];
+            let label = format!("{} binary stl", env!("CARGO_PKG_NAME"));
+            header[..label.len()].copy_from_slice(label.as_bytes());
+            file.write_all(&header)?;
+            file.write_all(&(triangles.len() as u32).to_le_bytes())?;
+
+            for triangle in &triangles {
+                for value in face_normal(triangle).iter().chain(triangle.iter().flatten()) {
+                    file.write_all(&value.to_le_bytes())?;
+                }
+                // attribute byte count, unused
+                file.write_all(&0u16.to_le_bytes())?;
+            }
+        }
+        StlFormat::Ascii => {
+            let name = env!("CARGO_PKG_NAME");
+            writeln!(file, "solid {}", name)?;
+            for triangle in &triangles {
+                let normal = face_normal(triangle);
+                writeln!(file, "  facet normal {:e} {:e} {:e}", normal[0], normal[1], normal[2])?;
+                writeln!(file, "    outer loop")?;
+                for vertex in triangle {
+                    writeln!(file, "      vertex {:e} {:e} {:e}", vertex[0], vertex[1], vertex[2])?;
+                }
+                writeln!(file, "    endloop")?;
+                writeln!(file, "  endfacet")?;
+            }
+            writeln!(file, "endsolid {}", name)?;
+        }
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+// glTF 2.0, .gltf writes the json next to a .bin file of the same name, .glb packs both into one binary file
+pub fn write_gltf(mesh: &MeshData, path: &str) -> Result<(), MeshExportError>
+{
+    let binary = check_mesh_extension(path, &["gltf", "glb"])? == "glb";
+    // Accessors and buffers must not be empty in glTF, so there is nothing valid to write
+    if mesh.positions.is_empty() || mesh.indices.is_empty() {
+        return Err(MeshExportError::EmptyMesh);
+    }
+
+    // Buffer layout: positions, normals, uvs, colours, then u32 indices, every part a multiple of 4 bytes
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut attributes = serde_json::Map::new();
+
+    let (min, max) = mesh.bounds();
+    let vertex_attributes: [(&str, &str, Vec<f32>); 4] = [
+        ("POSITION", "VEC3", mesh.positions.iter().flatten().copied().collect()),
+        ("NORMAL", "VEC3", mesh.normals.iter().flatten().copied().collect()),
+        ("TEXCOORD_0", "VEC2", mesh.uvs.iter().flatten().copied().collect()),
+        ("COLOR_0", "VEC4", mesh.colours.iter().flatten().copied().collect()),
+    ];
+    for (name, accessor_type, values) in vertex_attributes {
+        if values.is_empty() {
+            continue;
+        }
+        buffer_views.push(json!({ "buffer": 0, "byteOffset": buffer.len(), "byteLength": values.len() * 4, "target": 34962 }));
+        buffer.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+
+        let mut accessor = json!({ "bufferView": buffer_views.len() - 1, "componentType": 5126, "count": mesh.positions.len(), "type": accessor_type });
+        if name == "POSITION" {
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        accessors.push(accessor);
+        attributes.insert(name.to_string(), json!(accessors.len() - 1));
+    }
+
+    buffer_views.push(json!({ "buffer": 0, "byteOffset": buffer.len(), "byteLength": mesh.indices.len() * 4, "target": 34963 }));
+    buffer.extend(mesh.indices.iter().flat_map(|index| index.to_le_bytes()));
+    accessors.push(json!({ "bufferView": buffer_views.len() - 1, "componentType": 5125, "count": mesh.indices.len(), "type": "SCALAR" }));
+
+    let mut gltf_buffer = json!({ "byteLength": buffer.len() });
+    let bin_path = Path::new(path).with_extension("bin");
+    if !binary {
+        let bin_name = bin_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        gltf_buffer["uri"] = json!(bin_name);
+    }
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")) },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": attributes, "indices": accessors.len() - 1, "mode": 4 }] }],
+        "buffers": [gltf_buffer],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+    });
+    let mut json_bytes = serde_json::to_vec(&document).map_err(io::Error::from)?;
+
+    if !binary {
+        std::fs::write(path, &json_bytes)?;
+        std::fs::write(bin_path, &buffer)?;
+        return Ok(());
+    }
+
+    // glb chunks have to be 4 byte aligned, json is padded with spaces and the binary chunk with zeros
+    json_bytes.resize(json_bytes.len().next_multiple_of(4), b' ');
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+    let total_length = 12 + 8 + json_bytes.len() + 8 + buffer.len();
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"glTF")?;
+    file.write_all(&2u32.to_le_bytes())?;
+    file.write_all(&(total_length as u32).to_le_bytes())?;
+    file.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+    file.write_all(b"JSON")?;
+    file.write_all(&json_bytes)?;
+    file.write_all(&(buffer.len() as u32).to_le_bytes())?;
+    file.write_all(b"BIN\0")?;
+    file.write_all(&buffer)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+fn face_normal(triangle: &[[f32; 3]; 3]) -> [f32; 3]
+{
+    let [a, b, c] = triangle;
+    let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let normal = [ab[1] * ac[2] - ab[2] * ac[1], ab[2] * ac[0] - ab[0] * ac[2], ab[0] * ac[1] - ab[1] * ac[0]];
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if length == 0.0 {
+        return [0.0; 3];
+    }
+    return [normal[0] / length, normal[1] / length, normal[2] / length];
+}
+
+// Returns which of the allowed extensions the path has
+fn check_mesh_extension(path: &str, allowed: &[&'static str]) -> Result<&'static str, MeshExportError>
+{
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    match allowed.iter().find(|&&allowed| allowed == extension) {
+        Some(&extension) => Ok(extension),
+        None => Err(MeshExportError::BadExtension { path: path.to_string(), expected: allowed.to_vec() }),
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Mesh Export Error-------------------------------------------------------------------//
+#[derive(Debug)]
+pub enum MeshExportError
+{
+    BadExtension { path: String, expected: Vec<&'static str> },
+    EmptyMesh,
+    Io(io::Error),
+}
+
+impl fmt::Display for MeshExportError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            MeshExportError::BadExtension { path, expected } => write!(f, "{} does not have one of the extensions {:?}", path, expected),
+            MeshExportError::EmptyMesh => write!(f, "mesh has no triangles to export"),
+            MeshExportError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MeshExportError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self {
+            MeshExportError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MeshExportError
+{
+    fn from(error: io::Error) -> MeshExportError
+    {
+        MeshExportError::Io(error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::mesh::{build_terrain_mesh, TerrainSettings};
+    use crate::noise::{NoiseMap2D, NoiseMap3D};
+
+    fn terrain_mesh() -> MeshData
+    {
+        let mut map = NoiseMap2D::from_values(12, 9);
+        map.populate_with(&|point: [f64; 2]| (point[0] * 0.4).sin() + (point[1] * 0.3).cos());
+        let settings = TerrainSettings { spacing: 0.5, height_scale: 2.0, skirt_depth: 1.0, colour_stops: vec![(-2.0, [0.0, 0.0, 1.0, 1.0]), (2.0, [1.0; 4])] };
+        return build_terrain_mesh(&map, &settings);
+    }
+
+    fn sphere_mesh() -> MeshData
+    {
+        let mut map = NoiseMap3D::from_values(16, 16, 16);
+        map.populate_with(&|point: [f64; 3]| 5.0 - ((point[0] - 7.5).powi(2) + (point[1] - 7.5).powi(2) + (point[2] - 7.5).powi(2)).sqrt());
+        return map.extract_isosurface(0.0);
+    }
+
+    fn temp_path(name: &str) -> String
+    {
+        return std::env::temp_dir().join(format!("noise_project_export_{}_{}", std::process::id(), name)).to_str().unwrap().to_string();
+    }
+
+    fn assert_bounds(mesh: &MeshData, positions: &[[f32; 3]])
+    {
+        let parsed = MeshData { positions: positions.to_vec(), ..Default::default() };
+        let (min, max) = mesh.bounds();
+        let (parsed_min, parsed_max) = parsed.bounds();
+        assert!(min.iter().zip(parsed_min).all(|(a, b)| (a - b).abs() < 1e-4), "min {:?} != {:?}", min, parsed_min);
+        assert!(max.iter().zip(parsed_max).all(|(a, b)| (a - b).abs() < 1e-4), "max {:?} != {:?}", max, parsed_max);
+    }
+
+    #[test]
+    fn obj_round_trip()
+    {
+        for (name, mesh) in [("terrain.obj", terrain_mesh()), ("sphere.obj", sphere_mesh())] {
+            let path = temp_path(name);
+            write_obj(&mesh, &path).unwrap();
+
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let positions: Vec<[f32; 3]> = text
+                .lines()
+                .filter(|line| line.starts_with("v "))
+                .map(|line| {
+                    let values: Vec<f32> = line.split_whitespace().skip(1).map(|value| value.parse().unwrap()).collect();
+                    [values[0], values[1], values[2]]
+                })
+                .collect();
+            let normal_count = text.lines().filter(|line| line.starts_with("vn ")).count();
+            let uv_count = text.lines().filter(|line| line.starts_with("vt ")).count();
+            let faces: Vec<&str> = text.lines().filter(|line| line.starts_with("f ")).collect();
+
+            assert_eq!(positions.len(), mesh.vertex_count());
+            assert_eq!(normal_count, mesh.normals.len());
+            assert_eq!(uv_count, mesh.uvs.len());
+            assert_eq!(faces.len(), mesh.triangle_count());
+            for face in faces {
+                for corner in face.split_whitespace().skip(1) {
+                    let index: usize = corner.split('/').next().unwrap().parse().unwrap();
+                    assert!((1..=positions.len()).contains(&index));
+                }
+            }
+            assert_bounds(&mesh, &positions);
+        }
+    }
+
+    #[test]
+    fn binary_stl_round_trip()
+    {
+        let mesh = sphere_mesh();
+        let path = temp_path("sphere.stl");
+        write_stl(&mesh, &path, StlFormat::Binary).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let triangle_count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+        assert_eq!(triangle_count, mesh.triangle_count());
+        assert_eq!(bytes.len(), 84 + triangle_count * 50);
+
+        let positions: Vec<[f32; 3]> = bytes[84..]
+            .chunks_exact(50)
+            .flat_map(|record| (1..4).map(move |vertex| {
+                let value = |axis: usize| f32::from_le_bytes(record[vertex * 12 + axis * 4..vertex * 12 + axis * 4 + 4].try_into().unwrap());
+                [value(0), value(1), value(2)]
+            }))
+            .collect();
+        assert_bounds(&mesh, &positions);
+    }
+
+    #[test]
+    fn ascii_stl_round_trip()
+    {
+        let mesh = terrain_mesh();
+        let path = temp_path("terrain_ascii.stl");
+        write_stl(&mesh, &path, StlFormat::Ascii).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.starts_with("solid "));
+        assert!(text.trim_end().ends_with(&format!("endsolid {}", env!("CARGO_PKG_NAME"))));
+        assert_eq!(text.lines().filter(|line| line.trim_start().starts_with("facet normal")).count(), mesh.triangle_count());
+
+        let positions: Vec<[f32; 3]> = text
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("vertex "))
+            .map(|values| {
+                let values: Vec<f32> = values.split_whitespace().map(|value| value.parse().unwrap()).collect();
+                [values[0], values[1], values[2]]
+            })
+            .collect();
+        assert_eq!(positions.len(), mesh.triangle_count() * 3);
+        assert_bounds(&mesh, &positions);
+    }
+
+    fn check_gltf(mesh: &MeshData, document: &gltf::Document, buffer: &[u8])
+    {
+        let primitive = document.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = primitive.reader(|_| Some(buffer));
+
+        let positions: Vec<[f32; 3]> = reader.read_positions().unwrap().collect();
+        assert_eq!(positions.len(), mesh.vertex_count());
+        assert_eq!(reader.read_normals().unwrap().count(), mesh.normals.len());
+        assert_eq!(reader.read_indices().unwrap().into_u32().collect::<Vec<u32>>(), mesh.indices);
+        assert_bounds(mesh, &positions);
+
+        let bounds = primitive.bounding_box();
+        let (min, max) = mesh.bounds();
+        assert_eq!((bounds.min, bounds.max), (min, max));
+    }
+
+    #[test]
+    fn gltf_round_trip()
+    {
+        let mesh = terrain_mesh();
+        let path = temp_path("terrain.gltf");
+        write_gltf(&mesh, &path).unwrap();
+
+        let gltf = gltf::Gltf::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let buffer_path = Path::new(&path).with_extension("bin");
+        let buffer = std::fs::read(&buffer_path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&buffer_path).unwrap();
+        assert_eq!(gltf.buffers().next().unwrap().length(), buffer.len());
+        check_gltf(&mesh, &gltf.document, &buffer);
+        assert_eq!(gltf.meshes().next().unwrap().primitives().next().unwrap().reader(|_| Some(&buffer[..])).read_colors(0).unwrap().into_rgba_f32().count(), mesh.colours.len());
+    }
+
+    #[test]
+    fn glb_round_trip()
+    {
+        let mesh = sphere_mesh();
+        let path = temp_path("sphere.glb");
+        write_gltf(&mesh, &path).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize, bytes.len());
+        let gltf = gltf::Gltf::from_slice(&bytes).unwrap();
+        let buffer = gltf.blob.clone().unwrap();
+        check_gltf(&mesh, &gltf.document, &buffer);
+    }
+
+    #[test]
+    fn rejects_unknown_extensions()
+    {
+        let mesh = terrain_mesh();
+        assert!(matches!(write_obj(&mesh, &temp_path("bad_extension.txt")), Err(MeshExportError::BadExtension { .. })));
+        assert!(matches!(write_gltf(&mesh, &temp_path("bad_extension.obj")), Err(MeshExportError::BadExtension { .. })));
+    }
+
+    #[test]
+    fn empty_mesh_is_not_written_as_gltf()
+    {
+        for name in ["empty.gltf", "empty.glb"] {
+            let path = temp_path(name);
+            assert!(matches!(write_gltf(&MeshData::new(), &path), Err(MeshExportError::EmptyMesh)));
+            assert!(!std::path::Path::new(&path).exists());
+        }
+    }
+}
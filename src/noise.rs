@@ -1,30 +1,216 @@
 
-use std::{clone, fs::File, io::{Write, Read}, path::Path, usize};
+use std::{clone, f64::consts::TAU, fs::File, io::BufWriter, sync::atomic::{AtomicUsize, Ordering}, usize};
 
 use libnoise::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use bevy::{prelude::*, math::f32, render::{texture::ImageSampler, render_resource::{TextureDescriptor, TextureDimension, TextureFormat, Extent3d, TextureUsages}}};
 
+use crate::erosion::{erode, ErosionMaps, ErosionSettings};
+use crate::marching_cubes::extract_isosurface;
+use crate::mesh::{build_terrain_mesh, MeshData, TerrainSettings};
+use crate::normal_map::{NormalMap, NormalMapSettings};
+use crate::noise_file::{GridLayout, NoiseMapError, NoiseMetadata, SaveOptions, read_noise_file, write_noise_file};
+
 const DEPTH_SCALAR: f64 = 0.142;
+// Step of the central differences used by sources with no analytic gradient
+const GRADIENT_STEP: f64 = 1e-4;
+// libnoise's Perlin and Worley cast lattice cells to usize before wrapping them into their 256 entry permutation table, so every negative
+// cell hashes as cell 0 and the noise repeats and jumps left of / below the origin. Both repeat every 256 cells on the non-negative side,
+// so coordinates below the lowest cell a source reads are moved up by whole periods and everything else is left exactly as it was
+const LATTICE_PERIOD: f64 = 256.0;
+
+fn wrap_lattice<const N: usize>(point: [f64; N], lowest_cell: f64) -> [f64; N]
+{
+    return point.map(|coordinate| if coordinate < lowest_cell { coordinate.rem_euclid(LATTICE_PERIOD) + LATTICE_PERIOD } else { coordinate });
+}
+
+
+//-------------------------------------------------------------------Defining Fractal Mode-------------------------------------------------------------------//
+// How the octaves of a generator are combined, offset and gain only apply to the multifractal modes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FractalMode
+{
+    Fbm,
+    Ridged { offset: f32, gain: f32 },
+    Billow,
+    Turbulence,
+    HybridMulti { offset: f32, gain: f32 },
+}
+
 
 //-------------------------------------------------------------------Defining Noise Values-------------------------------------------------------------------//
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct NoiseValues
 {
     pub octaves: usize,
     pub scale: f32,
     pub persistence: f32,
-    pub lacunarity: f32
+    pub lacunarity: f32,
+    pub fractal_mode: FractalMode,
+}
+impl NoiseValues
+{
+    // Runs the octave loop, sample is called with the frequency of each octave and returns the raw source value
+    pub fn accumulate<F>(&self, mut sample: F) -> f32
+    where
+        F: FnMut(f64) -> f32,
+    {
+        let mut sum = 0.0;
+        let mut amplitude: f32 = 1.0;
+        let mut frequency = self.scale as f64;
+        // Ridged and hybrid multifractals let each octave scale how much detail the next one adds
+        let mut weight: f32 = 1.0;
+
+        for octave in 0..self.octaves {
+            let noise = sample(frequency);
+
+            match self.fractal_mode {
+                FractalMode::Fbm => sum += noise * amplitude,
+                FractalMode::Billow => sum += (2.0 * noise.abs() - 1.0) * amplitude,
+                FractalMode::Turbulence => sum += noise.abs() * amplitude,
+                FractalMode::Ridged { offset, gain } => {
+                    let signal = (offset - noise.abs()).powi(2) * weight;
+                    weight = (signal * gain).clamp(0.0, 1.0);
+                    sum += signal * amplitude;
+                }
+                FractalMode::HybridMulti { offset, gain } => {
+                    let signal = (noise + offset) * amplitude;
+                    if octave == 0 {
+                        sum = signal;
+                        weight = signal;
+                    } else {
+                        weight = weight.min(1.0);
+                        sum += weight * signal;
+                        weight *= gain * signal;
+                    }
+                }
+            }
+
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity as f64;
+        }
+
+        sum
+    }
+
+    // Same octave loop as accumulate, also carrying the gradient through each fractal mode with the chain rule.
+    // sample returns the raw source value and its gradient with respect to the point the generator was asked for
+    pub fn accumulate_with_gradient<F, const N: usize>(&self, mut sample: F) -> (f32, [f32; N])
+    where
+        F: FnMut(f64) -> (f32, [f32; N]),
+    {
+        let mut sum = 0.0;
+        let mut sum_gradient = [0.0; N];
+        let mut amplitude: f32 = 1.0;
+        let mut frequency = self.scale as f64;
+        let mut weight: f32 = 1.0;
+        let mut weight_gradient = [0.0; N];
+
+        for octave in 0..self.octaves {
+            let (noise, gradient) = sample(frequency);
+
+            match self.fractal_mode {
+                FractalMode::Fbm => {
+                    sum += noise * amplitude;
+                    add_scaled(&mut sum_gradient, &gradient, amplitude);
+                }
+                FractalMode::Billow => {
+                    sum += (2.0 * noise.abs() - 1.0) * amplitude;
+                    add_scaled(&mut sum_gradient, &gradient, 2.0 * noise.signum() * amplitude);
+                }
+                FractalMode::Turbulence => {
+                    sum += noise.abs() * amplitude;
+                    add_scaled(&mut sum_gradient, &gradient, noise.signum() * amplitude);
+                }
+                FractalMode::Ridged { offset, gain } => {
+                    let ridge = offset - noise.abs();
+                    let signal = ridge.powi(2) * weight;
+                    let signal_gradient: [f32; N] = std::array::from_fn(|axis| {
+                        -2.0 * ridge * noise.signum() * gradient[axis] * weight + ridge.powi(2) * weight_gradient[axis]
+                    });
+                    // The weight stops changing with the point wherever the clamp kicks in
+                    let unclamped = signal * gain;
+                    weight = unclamped.clamp(0.0, 1.0);
+                    weight_gradient = if (0.0..=1.0).contains(&unclamped) { signal_gradient.map(|slope| slope * gain) } else { [0.0; N] };
+                    sum += signal * amplitude;
+                    add_scaled(&mut sum_gradient, &signal_gradient, amplitude);
+                }
+                FractalMode::HybridMulti { offset, gain } => {
+                    let signal = (noise + offset) * amplitude;
+                    let signal_gradient = gradient.map(|slope| slope * amplitude);
+                    if octave == 0 {
+                        sum = signal;
+                        sum_gradient = signal_gradient;
+                        weight = signal;
+                        weight_gradient = signal_gradient;
+                    } else {
+                        if weight > 1.0 {
+                            weight = 1.0;
+                            weight_gradient = [0.0; N];
+                        }
+                        sum += weight * signal;
+                        // Product rule on weight * signal, used for both the sum and the next weight
+                        let product_gradient: [f32; N] = std::array::from_fn(|axis| weight_gradient[axis] * signal + weight * signal_gradient[axis]);
+                        add_scaled(&mut sum_gradient, &product_gradient, 1.0);
+                        weight_gradient = product_gradient.map(|slope| slope * gain);
+                        weight *= gain * signal;
+                    }
+                }
+            }
+
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity as f64;
+        }
+
+        (sum, sum_gradient)
+    }
+}
+
+fn add_scaled<const N: usize>(target: &mut [f32; N], gradient: &[f32; N], scale: f32)
+{
+    for (target, slope) in target.iter_mut().zip(gradient) {
+        *target += slope * scale;
+    }
+}
+
+// Central difference gradient of any sampling function, the fallback for sources without an analytic one
+fn finite_difference_gradient<F, const N: usize>(sample: F, point: [f64; N]) -> [f64; N]
+where
+    F: Fn([f64; N]) -> f64,
+{
+    return std::array::from_fn(|axis| {
+        let mut ahead = point;
+        let mut behind = point;
+        ahead[axis] += GRADIENT_STEP;
+        behind[axis] -= GRADIENT_STEP;
+        (sample(ahead) - sample(behind)) / (2.0 * GRADIENT_STEP)
+    });
 }
 
 
 //-------------------------------------------------------------------Defining Noise Source 2D-------------------------------------------------------------------//
-trait NoiseSource2D {
+pub trait NoiseSource2D {
     fn sample(&self, point: [f64; 2]) -> f64;
+
+    // Value and gradient at point, sources that can differentiate themselves override the finite difference fallback
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        return (self.sample(point), finite_difference_gradient(|point| self.sample(point), point));
+    }
+}
+
+impl<F> NoiseSource2D for F
+where
+    F: Fn([f64; 2]) -> f64,
+{
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return self(point);
+    }
 }
 
 impl NoiseSource2D for Perlin<2> {
     fn sample(&self, point: [f64; 2]) -> f64 {
-        return libnoise::Generator::sample(self, point);
+        return libnoise::Generator::sample(self, wrap_lattice(point, 0.0));
     }
 }
 
@@ -36,12 +222,37 @@ impl NoiseSource2D for Simplex<2> {
 
 impl NoiseSource2D for Worley<2> {
     fn sample(&self, point: [f64; 2]) -> f64 {
-        return libnoise::Generator::sample(self, point);
+        // Worley also reads the cell below the point's own
+        return libnoise::Generator::sample(self, wrap_lattice(point, 1.0));
+    }
+}
+impl NoiseSource2D for Box<dyn NoiseSource2D + Send + Sync> {
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return (**self).sample(point);
+    }
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        return (**self).sample_with_gradient(point);
+    }
+}
+impl<S> NoiseSource2D for NoiseGenerator2D<S>
+where
+    S: NoiseSource2D,
+{
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return self.get_value_at_point(point) as f64;
+    }
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        let (value, gradient) = self.get_value_and_gradient_at_point(point);
+        return (value as f64, gradient.map(|slope| slope as f64));
     }
 }
 impl NoiseSource2D for CombinedNoiseGenerator2D {
     fn sample(&self, point: [f64; 2]) -> f64 {
-        return CombinedNoiseGenerator2D::get_weighted_value(&self, point[0] as i32, point[1] as i32) as f64;
+        return self.get_weighted_value_at_point(point) as f64;
+    }
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        let (value, gradient) = self.get_weighted_value_and_gradient_at_point(point);
+        return (value as f64, gradient.map(|slope| slope as f64));
     }
 }
 
@@ -60,21 +271,28 @@ where
     S: NoiseSource2D,
 {
     pub fn get_value_at(&self, x: i32, y: i32) -> f32 {
-        let mut sum = 0.0;
-        let mut amplitude: f32 = 1.0;
-        let mut frequency = self.values.scale;
+        return self.get_value_at_point([x as f64, y as f64]);
+    }
 
-        for _ in 0..self.values.octaves {
-            let new_x: f32 = x as f32 * frequency;
-            let new_y: f32 = y as f32 * frequency;
-            let sample: f64 = self.source.sample([new_x as f64, new_y as f64]);
-            sum += sample as f32 * amplitude;
+    // Continuous version of get_value_at, integer points give the same result
+    pub fn get_value_at_point(&self, point: [f64; 2]) -> f32 {
+        self.values.accumulate(|frequency| {
+            let new_x = point[0] * frequency;
+            let new_y = point[1] * frequency;
+            self.source.sample([new_x, new_y]) as f32
+        })
+    }
 
-            amplitude *= self.values.persistence;
-            frequency *= self.values.lacunarity;
-        }
+    pub fn get_value_and_gradient(&self, x: i32, y: i32) -> (f32, [f32; 2]) {
+        return self.get_value_and_gradient_at_point([x as f64, y as f64]);
+    }
 
-        sum
+    // Value and gradient with respect to point, each octave's source gradient is scaled by the frequency it was sampled at
+    pub fn get_value_and_gradient_at_point(&self, point: [f64; 2]) -> (f32, [f32; 2]) {
+        self.values.accumulate_with_gradient(|frequency| {
+            let (value, gradient) = self.source.sample_with_gradient([point[0] * frequency, point[1] * frequency]);
+            (value as f32, gradient.map(|slope| (slope * frequency) as f32))
+        })
     }
 }
 
@@ -93,31 +311,70 @@ impl CombinedNoiseGenerator2D
 {
     pub fn get_value(&self, x: i32, y: i32) -> f32
     {
-        let perlin = self.perlin_generator.get_value_at(x, y);
-        let simplex = self.simplex_generator.get_value_at(x, y);
-        let worley = self.worley_generator.get_value_at(x, y);
+        return self.get_value_at_point([x as f64, y as f64]);
+    }
+    pub fn get_weighted_value(&self, x: i32, y: i32) -> f32
+    {
+        return self.get_weighted_value_at_point([x as f64, y as f64]);
+    }
+    pub fn get_value_at_point(&self, point: [f64; 2]) -> f32
+    {
+        let perlin = self.perlin_generator.get_value_at_point(point);
+        let simplex = self.simplex_generator.get_value_at_point(point);
+        let worley = self.worley_generator.get_value_at_point(point);
         
         return perlin + simplex + worley;
     }
-    pub fn get_weighted_value(&self, x: i32, y: i32) -> f32
+    pub fn get_weighted_value_at_point(&self, point: [f64; 2]) -> f32
     {
-        let perlin = self.perlin_generator.get_value_at(x, y) * self.perlin_weight;
-        let simplex = self.simplex_generator.get_value_at(x, y) * self.simplex_weight;
-        let worley = self.worley_generator.get_value_at(x, y) * self.worley_weight;
+        let perlin = self.perlin_generator.get_value_at_point(point) * self.perlin_weight;
+        let simplex = self.simplex_generator.get_value_at_point(point) * self.simplex_weight;
+        let worley = self.worley_generator.get_value_at_point(point) * self.worley_weight;
         let ratio = 1.0 / (self.perlin_weight + self.simplex_weight + self.worley_weight);
         return perlin * ratio + simplex * ratio + worley * ratio;
     }
+    pub fn get_weighted_value_and_gradient_at_point(&self, point: [f64; 2]) -> (f32, [f32; 2])
+    {
+        let ratio = 1.0 / (self.perlin_weight + self.simplex_weight + self.worley_weight);
+        let parts = [
+            (self.perlin_generator.get_value_and_gradient_at_point(point), self.perlin_weight * ratio),
+            (self.simplex_generator.get_value_and_gradient_at_point(point), self.simplex_weight * ratio),
+            (self.worley_generator.get_value_and_gradient_at_point(point), self.worley_weight * ratio),
+        ];
+
+        let mut value = 0.0;
+        let mut gradient = [0.0; 2];
+        for ((part_value, part_gradient), weight) in parts {
+            value += part_value * weight;
+            add_scaled(&mut gradient, &part_gradient, weight);
+        }
+        return (value, gradient);
+    }
 }
 
 
 //-------------------------------------------------------------------Defining Noise Source 3D-------------------------------------------------------------------//
-trait NoiseSource3D {
+pub trait NoiseSource3D {
     fn sample(&self, point: [f64; 3]) -> f64;
+
+    // Value and gradient at point, sources that can differentiate themselves override the finite difference fallback
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        return (self.sample(point), finite_difference_gradient(|point| self.sample(point), point));
+    }
+}
+
+impl<F> NoiseSource3D for F
+where
+    F: Fn([f64; 3]) -> f64,
+{
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self(point);
+    }
 }
 
 impl NoiseSource3D for Perlin<3> {
     fn sample(&self, point: [f64; 3]) -> f64 {
-        return libnoise::Generator::sample(self, point);
+        return libnoise::Generator::sample(self, wrap_lattice(point, 0.0));
     }
 }
 
@@ -129,7 +386,37 @@ impl NoiseSource3D for Simplex<3> {
 
 impl NoiseSource3D for Worley<3> {
     fn sample(&self, point: [f64; 3]) -> f64 {
-        return libnoise::Generator::sample(self, point);
+        // Worley also reads the cell below the point's own
+        return libnoise::Generator::sample(self, wrap_lattice(point, 1.0));
+    }
+}
+impl NoiseSource3D for Box<dyn NoiseSource3D + Send + Sync> {
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return (**self).sample(point);
+    }
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        return (**self).sample_with_gradient(point);
+    }
+}
+impl<S> NoiseSource3D for NoiseGenerator3D<S>
+where
+    S: NoiseSource3D,
+{
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self.get_value_at_point(point) as f64;
+    }
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        let (value, gradient) = self.get_value_and_gradient_at_point(point);
+        return (value as f64, gradient.map(|slope| slope as f64));
+    }
+}
+impl NoiseSource3D for CombinedNoiseGenerator3D {
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self.get_weighted_value_at_point(point) as f64;
+    }
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        let (value, gradient) = self.get_weighted_value_and_gradient_at_point(point);
+        return (value as f64, gradient.map(|slope| slope as f64));
     }
 }
 
@@ -148,22 +435,30 @@ where
     S: NoiseSource3D,
 {
     pub fn get_value_at(&self, x: i32, y: i32, z: i32) -> f32 {
-        let mut sum = 0.0;
-        let mut amplitude: f32 = 1.0;
-        let mut frequency = self.values.scale;
+        return self.get_value_at_point([x as f64, y as f64, z as f64]);
+    }
 
-        for _ in 0..self.values.octaves {
-            let new_x: f32 = x as f32 * frequency;
-            let new_y: f32 = y as f32 * frequency;
-            let new_z: f32 = z as f32 * frequency * DEPTH_SCALAR as f32;
-            let sample: f64 = self.source.sample([new_x as f64, new_y as f64, new_z as f64]);
-            sum += sample as f32 * amplitude;
+    // Continuous version of get_value_at, integer points give the same result
+    pub fn get_value_at_point(&self, point: [f64; 3]) -> f32 {
+        self.values.accumulate(|frequency| {
+            let new_x = point[0] * frequency;
+            let new_y = point[1] * frequency;
+            let new_z = point[2] * frequency * DEPTH_SCALAR;
+            self.source.sample([new_x, new_y, new_z]) as f32
+        })
+    }
 
-            amplitude *= self.values.persistence;
-            frequency *= self.values.lacunarity;
-        }
+    pub fn get_value_and_gradient(&self, x: i32, y: i32, z: i32) -> (f32, [f32; 3]) {
+        return self.get_value_and_gradient_at_point([x as f64, y as f64, z as f64]);
+    }
 
-        sum
+    // Value and gradient with respect to point, z is also scaled by DEPTH_SCALAR before it reaches the source
+    pub fn get_value_and_gradient_at_point(&self, point: [f64; 3]) -> (f32, [f32; 3]) {
+        self.values.accumulate_with_gradient(|frequency| {
+            let scales = [frequency, frequency, frequency * DEPTH_SCALAR];
+            let (value, gradient) = self.source.sample_with_gradient([point[0] * scales[0], point[1] * scales[1], point[2] * scales[2]]);
+            (value as f32, std::array::from_fn(|axis| (gradient[axis] * scales[axis]) as f32))
+        })
     }
 }
 
@@ -182,37 +477,456 @@ impl CombinedNoiseGenerator3D
 {
     pub fn get_value(&self, x: i32, y: i32, z: i32) -> f32
     {
-        let perlin = self.perlin_generator.get_value_at(x, y, z );
-        let simplex = self.simplex_generator.get_value_at(x, y, z);
-        let worley = self.worley_generator.get_value_at(x, y, z);
+        return self.get_value_at_point([x as f64, y as f64, z as f64]);
+    }
+    pub fn get_weighted_value(&self, x: i32, y: i32, z: i32) -> f32
+    {
+        return self.get_weighted_value_at_point([x as f64, y as f64, z as f64]);
+    }
+    pub fn get_value_at_point(&self, point: [f64; 3]) -> f32
+    {
+        let perlin = self.perlin_generator.get_value_at_point(point);
+        let simplex = self.simplex_generator.get_value_at_point(point);
+        let worley = self.worley_generator.get_value_at_point(point);
         
         return perlin + simplex + worley;
     }
-    pub fn get_weighted_value(&self, x: i32, y: i32, z: i32) -> f32
+    pub fn get_weighted_value_at_point(&self, point: [f64; 3]) -> f32
     {
-        let perlin = self.perlin_generator.get_value_at(x, y, z) * self.perlin_weight;
-        let simplex = self.simplex_generator.get_value_at(x, y, z) * self.simplex_weight;
-        let worley = self.worley_generator.get_value_at(x, y, z) * self.worley_weight;
+        let perlin = self.perlin_generator.get_value_at_point(point) * self.perlin_weight;
+        let simplex = self.simplex_generator.get_value_at_point(point) * self.simplex_weight;
+        let worley = self.worley_generator.get_value_at_point(point) * self.worley_weight;
         let ratio = 1.0 / (self.perlin_weight + self.simplex_weight + self.worley_weight);
         return perlin * ratio + simplex * ratio + worley * ratio;
     }
+    pub fn get_weighted_value_and_gradient_at_point(&self, point: [f64; 3]) -> (f32, [f32; 3])
+    {
+        let ratio = 1.0 / (self.perlin_weight + self.simplex_weight + self.worley_weight);
+        let parts = [
+            (self.perlin_generator.get_value_and_gradient_at_point(point), self.perlin_weight * ratio),
+            (self.simplex_generator.get_value_and_gradient_at_point(point), self.simplex_weight * ratio),
+            (self.worley_generator.get_value_and_gradient_at_point(point), self.worley_weight * ratio),
+        ];
+
+        let mut value = 0.0;
+        let mut gradient = [0.0; 3];
+        for ((part_value, part_gradient), weight) in parts {
+            value += part_value * weight;
+            add_scaled(&mut gradient, &part_gradient, weight);
+        }
+        return (value, gradient);
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Domain Warp 2D-------------------------------------------------------------------//
+// Each axis samples the warpers at a different offset so the x and y displacements are not correlated
+const WARP_OFFSETS_2D: [[f64; 2]; 2] = [[0.0, 0.0], [5.2, 1.3]];
+
+// Same displacement as warp_point, also carrying the Jacobian of the warped point with respect to the original one (jacobian[axis][input axis]).
+// sample returns a warper's value and gradient at a point
+fn warp_with_jacobian<W, F, const N: usize>(point: [f64; N], warpers: &[W], offsets: &[[f64; N]; N], strength: f64, iterations: usize, sample: F) -> ([f64; N], [[f64; N]; N])
+where
+    F: Fn(&W, [f64; N]) -> (f64, [f64; N]),
+{
+    let mut warped = point;
+    let mut jacobian: [[f64; N]; N] = std::array::from_fn(|axis| std::array::from_fn(|input| if axis == input { 1.0 } else { 0.0 }));
+    for _ in 0..iterations {
+        for warper in warpers {
+            let samples: [(f64, [f64; N]); N] = std::array::from_fn(|axis| sample(warper, std::array::from_fn(|input| warped[input] + offsets[axis][input])));
+            // Every displacement is read at the old warped point, so each row builds on the old Jacobian
+            let old_jacobian = jacobian;
+            for (axis, (offset, gradient)) in samples.iter().enumerate() {
+                warped[axis] += offset * strength;
+                for input in 0..N {
+                    jacobian[axis][input] += strength * (0..N).map(|inner| gradient[inner] * old_jacobian[inner][input]).sum::<f64>();
+                }
+            }
+        }
+    }
+    return (warped, jacobian);
+}
+
+// Chain rule from the gradient at the warped point back to the point before warping
+fn unwarp_gradient<const N: usize>(gradient: [f64; N], jacobian: &[[f64; N]; N]) -> [f64; N]
+{
+    return std::array::from_fn(|input| (0..N).map(|axis| gradient[axis] * jacobian[axis][input]).sum());
+}
+
+pub struct DomainWarp2D<S, W>
+where
+    S: NoiseSource2D,
+    W: NoiseSource2D,
+{
+    pub source: S,
+    pub warpers: Vec<W>,
+    pub strength: f64,
+    pub iterations: usize,
+}
+impl<S, W> DomainWarp2D<S, W>
+where
+    S: NoiseSource2D,
+    W: NoiseSource2D,
+{
+    // Every iteration displaces the point by each warper in turn, feeding the displaced point into the next one
+    pub fn warp_point(&self, point: [f64; 2]) -> [f64; 2] {
+        let mut warped = point;
+        for _ in 0..self.iterations {
+            for warper in &self.warpers {
+                let offset_x = warper.sample([warped[0] + WARP_OFFSETS_2D[0][0], warped[1] + WARP_OFFSETS_2D[0][1]]);
+                let offset_y = warper.sample([warped[0] + WARP_OFFSETS_2D[1][0], warped[1] + WARP_OFFSETS_2D[1][1]]);
+                warped = [warped[0] + offset_x * self.strength, warped[1] + offset_y * self.strength];
+            }
+        }
+        warped
+    }
+
+    pub fn get_value_at(&self, x: i32, y: i32) -> f32 {
+        return self.get_value_at_point([x as f64, y as f64]);
+    }
+
+    pub fn get_value_at_point(&self, point: [f64; 2]) -> f32 {
+        return self.source.sample(self.warp_point(point)) as f32;
+    }
+}
+
+impl<S, W> NoiseSource2D for DomainWarp2D<S, W>
+where
+    S: NoiseSource2D,
+    W: NoiseSource2D,
+{
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return self.source.sample(self.warp_point(point));
+    }
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        let (warped, jacobian) = warp_with_jacobian(point, &self.warpers, &WARP_OFFSETS_2D, self.strength, self.iterations, |warper, point| warper.sample_with_gradient(point));
+        let (value, gradient) = self.source.sample_with_gradient(warped);
+        return (value, unwarp_gradient(gradient, &jacobian));
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Domain Warp 3D-------------------------------------------------------------------//
+const WARP_OFFSETS_3D: [[f64; 3]; 3] = [[0.0, 0.0, 0.0], [5.2, 1.3, 2.8], [1.7, 9.2, 4.6]];
+
+pub struct DomainWarp3D<S, W>
+where
+    S: NoiseSource3D,
+    W: NoiseSource3D,
+{
+    pub source: S,
+    pub warpers: Vec<W>,
+    pub strength: f64,
+    pub iterations: usize,
+}
+impl<S, W> DomainWarp3D<S, W>
+where
+    S: NoiseSource3D,
+    W: NoiseSource3D,
+{
+    pub fn warp_point(&self, point: [f64; 3]) -> [f64; 3] {
+        let mut warped = point;
+        for _ in 0..self.iterations {
+            for warper in &self.warpers {
+                let mut offset = [0.0; 3];
+                for (axis, axis_offset) in WARP_OFFSETS_3D.iter().enumerate() {
+                    offset[axis] = warper.sample([warped[0] + axis_offset[0], warped[1] + axis_offset[1], warped[2] + axis_offset[2]]);
+                }
+                warped = [
+                    warped[0] + offset[0] * self.strength,
+                    warped[1] + offset[1] * self.strength,
+                    warped[2] + offset[2] * self.strength,
+                ];
+            }
+        }
+        warped
+    }
+
+    pub fn get_value_at(&self, x: i32, y: i32, z: i32) -> f32 {
+        return self.get_value_at_point([x as f64, y as f64, z as f64]);
+    }
+
+    pub fn get_value_at_point(&self, point: [f64; 3]) -> f32 {
+        return self.source.sample(self.warp_point(point)) as f32;
+    }
+}
+
+impl<S, W> NoiseSource3D for DomainWarp3D<S, W>
+where
+    S: NoiseSource3D,
+    W: NoiseSource3D,
+{
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self.source.sample(self.warp_point(point));
+    }
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        let (warped, jacobian) = warp_with_jacobian(point, &self.warpers, &WARP_OFFSETS_3D, self.strength, self.iterations, |warper, point| warper.sample_with_gradient(point));
+        let (value, gradient) = self.source.sample_with_gradient(warped);
+        return (value, unwarp_gradient(gradient, &jacobian));
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Noise Source 4D-------------------------------------------------------------------//
+// Only used as the higher dimensional space that tileable and looping generators wrap around
+pub trait NoiseSource4D {
+    fn sample(&self, point: [f64; 4]) -> f64;
+}
+
+impl<F> NoiseSource4D for F
+where
+    F: Fn([f64; 4]) -> f64,
+{
+    fn sample(&self, point: [f64; 4]) -> f64 {
+        return self(point);
+    }
+}
+
+impl NoiseSource4D for Perlin<4> {
+    fn sample(&self, point: [f64; 4]) -> f64 {
+        return libnoise::Generator::sample(self, wrap_lattice(point, 0.0));
+    }
+}
+
+impl NoiseSource4D for Simplex<4> {
+    fn sample(&self, point: [f64; 4]) -> f64 {
+        return libnoise::Generator::sample(self, point);
+    }
+}
+
+impl NoiseSource4D for Worley<4> {
+    fn sample(&self, point: [f64; 4]) -> f64 {
+        // Worley also reads the cell below the point's own
+        return libnoise::Generator::sample(self, wrap_lattice(point, 1.0));
+    }
+}
+impl NoiseSource4D for Box<dyn NoiseSource4D + Send + Sync> {
+    fn sample(&self, point: [f64; 4]) -> f64 {
+        return (**self).sample(point);
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Tileable Noise Generator 2D-------------------------------------------------------------------//
+// Wraps x and y each around a circle of the 4D source (a torus), so the noise repeats every period units without a seam.
+// The circles have a circumference of period, so features keep roughly the size they have in NoiseGenerator2D
+pub struct TileableNoiseGenerator2D<S>
+where
+    S: NoiseSource4D,
+{
+    pub source: S,
+    pub values: NoiseValues,
+    pub period: [f64; 2],
+}
+
+impl<S> TileableNoiseGenerator2D<S>
+where
+    S: NoiseSource4D,
+{
+    pub fn get_value_at(&self, x: i32, y: i32) -> f32 {
+        return self.get_value_at_point([x as f64, y as f64]);
+    }
+
+    pub fn get_value_at_point(&self, point: [f64; 2]) -> f32 {
+        let (sin_x, cos_x) = (point[0] / self.period[0] * TAU).sin_cos();
+        let (sin_y, cos_y) = (point[1] / self.period[1] * TAU).sin_cos();
+        let radius_x = self.period[0] / TAU;
+        let radius_y = self.period[1] / TAU;
+
+        self.values.accumulate(|frequency| {
+            // libnoise sources only hash non-negative lattice cells correctly, so the torus is kept clear of the negative axes
+            let offset_x = radius_x * frequency + 1.0;
+            let offset_y = radius_y * frequency + 1.0;
+            let torus_point = [
+                offset_x + cos_x * radius_x * frequency,
+                offset_x + sin_x * radius_x * frequency,
+                offset_y + cos_y * radius_y * frequency,
+                offset_y + sin_y * radius_y * frequency,
+            ];
+            self.source.sample(torus_point) as f32
+        })
+    }
+}
+
+impl<S> NoiseSource2D for TileableNoiseGenerator2D<S>
+where
+    S: NoiseSource4D,
+{
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return self.get_value_at_point(point) as f64;
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Looping Noise Generator 3D-------------------------------------------------------------------//
+// Walks z around a circle in the z / w plane of the 4D source, so slice z and slice z + period are identical and an animation over z loops forever.
+// The circle has a circumference of period scaled like the z axis of NoiseGenerator3D, so it changes about as fast between slices
+pub struct LoopingNoiseGenerator3D<S>
+where
+    S: NoiseSource4D,
+{
+    pub source: S,
+    pub values: NoiseValues,
+    pub period: f64,
+}
+
+impl<S> LoopingNoiseGenerator3D<S>
+where
+    S: NoiseSource4D,
+{
+    pub fn get_value_at(&self, x: i32, y: i32, z: i32) -> f32 {
+        return self.get_value_at_point([x as f64, y as f64, z as f64]);
+    }
+
+    pub fn get_value_at_point(&self, point: [f64; 3]) -> f32 {
+        let (sin_z, cos_z) = (point[2] / self.period * TAU).sin_cos();
+        let radius = self.period * DEPTH_SCALAR / TAU;
+
+        self.values.accumulate(|frequency| {
+            // Kept clear of the negative axes for the same reason as TileableNoiseGenerator2D
+            let offset = radius * frequency + 1.0;
+            let loop_point = [
+                point[0] * frequency,
+                point[1] * frequency,
+                offset + cos_z * radius * frequency,
+                offset + sin_z * radius * frequency,
+            ];
+            self.source.sample(loop_point) as f32
+        })
+    }
+}
+
+impl<S> NoiseSource3D for LoopingNoiseGenerator3D<S>
+where
+    S: NoiseSource4D,
+{
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self.get_value_at_point(point) as f64;
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Noise Statistics-------------------------------------------------------------------//
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseStatistics
+{
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+}
+impl NoiseStatistics
+{
+    // Single pass over the buffer, accumulating in f64 so large volumes do not lose precision, an empty buffer gives all zeros
+    pub fn from_buffer(buffer: &[f32]) -> NoiseStatistics
+    {
+        if buffer.is_empty() {
+            return NoiseStatistics { min: 0.0, max: 0.0, mean: 0.0, std_dev: 0.0 };
+        }
+
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let mut sum = 0.0f64;
+        let mut sum_of_squares = 0.0f64;
+        for &value in buffer {
+            min = min.min(value);
+            max = max.max(value);
+            sum += value as f64;
+            sum_of_squares += value as f64 * value as f64;
+        }
+
+        let count = buffer.len() as f64;
+        let mean = sum / count;
+        let variance = (sum_of_squares / count - mean * mean).max(0.0);
+
+        NoiseStatistics { min, max, mean: mean as f32, std_dev: variance.sqrt() as f32 }
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Noise Buffer Operations-------------------------------------------------------------------//
+// Shared by NoiseMap2D and NoiseMap3D, each cell is independent so they run across all cores
+fn normalise_buffer(buffer: &mut [f32], min: f32, max: f32)
+{
+    let statistics = NoiseStatistics::from_buffer(buffer);
+    let source_range = statistics.max - statistics.min;
+    if source_range <= 0.0 {
+        buffer.par_iter_mut().for_each(|value| *value = min);
+        return;
+    }
+    let scale = (max - min) / source_range;
+    buffer.par_iter_mut().for_each(|value| *value = min + (*value - statistics.min) * scale);
+}
+
+fn clamp_buffer(buffer: &mut [f32], min: f32, max: f32)
+{
+    buffer.par_iter_mut().for_each(|value| *value = value.clamp(min, max));
+}
+
+fn remap_buffer<F>(buffer: &mut [f32], curve: F)
+where
+    F: Fn(f32) -> f32 + Sync,
+{
+    buffer.par_iter_mut().for_each(|value| *value = curve(*value));
+}
+
+
+//-------------------------------------------------------------------Defining Value Range-------------------------------------------------------------------//
+// How noise values are mapped onto the 0..1 range of an exported image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueRange
+{
+    Fixed { min: f32, max: f32 },
+    Auto,
+}
+impl ValueRange
+{
+    pub fn resolve(&self, buffer: &[f32]) -> (f32, f32)
+    {
+        match *self {
+            ValueRange::Fixed { min, max } => (min, max),
+            ValueRange::Auto => {
+                let statistics = NoiseStatistics::from_buffer(buffer);
+                (statistics.min, statistics.max)
+            }
+        }
+    }
+}
+
+fn normalise_value(value: f32, min: f32, max: f32) -> f32
+{
+    if max <= min {
+        return 0.0;
+    }
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PngBitDepth
+{
+    Eight,
+    Sixteen,
 }
 
 
 //-------------------------------------------------------------------Defining Noise Map 2D-------------------------------------------------------------------//
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 pub struct NoiseMap2D
 {
     pub width: usize,
     pub height: usize,
-    pub buffer: Vec<f32>
+    pub buffer: Vec<f32>,
+    pub metadata: Option<NoiseMetadata>,
+    // World position of cell (0, 0) and the distance between neighbouring cells, populate samples origin + index * cell_size
+    pub origin: [f64; 2],
+    pub cell_size: f64,
 } 
 impl NoiseMap2D
 {   
     pub fn from_values(width: usize, height: usize) -> NoiseMap2D
     {
         let buffer = vec![0.0 as f32; width * height];                 
-        return NoiseMap2D{ width, height, buffer};
+        return NoiseMap2D{ width, height, buffer, metadata: None, origin: [0.0; 2], cell_size: 1.0 };
     }
     pub fn initialize(&mut self)
     {
@@ -220,12 +934,116 @@ impl NoiseMap2D
     }
     pub fn get_value_at(&self, x: u32, y: u32) -> f32
     {
-        return self.buffer[((y as usize * self.height) + x as usize)]
+        return self.buffer[((y as usize * self.width) + x as usize)]
     }
     pub fn set_value_at(&mut self, x: u32, y: u32, value: f32)
     {
-        self.buffer[((y as usize * self.height) + x as usize)] = value;
+        self.buffer[((y as usize * self.width) + x as usize)] = value;
+    }
+    pub fn world_position(&self, x: usize, y: usize) -> [f64; 2]
+    {
+        return [self.origin[0] + x as f64 * self.cell_size, self.origin[1] + y as f64 * self.cell_size];
+    }
+    // Samples the source once per cell at its world position, closures taking a [f64; 2] point work as sources too
+    pub fn populate_with<S>(&mut self, source: &S)
+    where
+        S: NoiseSource2D + Sync + ?Sized,
+    {
+        self.populate_with_progress(source, |_, _| {});
     }
+    // Fills the map so its right edge continues into its left and its bottom into its top, for textures that repeat
+    pub fn populate_tileable<S>(&mut self, source: &S, values: &NoiseValues)
+    where
+        S: NoiseSource4D + Sync + ?Sized,
+    {
+        let generator = TileableNoiseGenerator2D {
+            source: |point: [f64; 4]| source.sample(point),
+            values: values.clone(),
+            period: [self.width as f64 * self.cell_size, self.height as f64 * self.cell_size],
+        };
+        self.populate_with(&generator);
+    }
+    // progress is called with (completed rows, total rows) from whichever thread finished the row
+    pub fn populate_with_progress<S, F>(&mut self, source: &S, progress: F)
+    where
+        S: NoiseSource2D + Sync + ?Sized,
+        F: Fn(usize, usize) + Sync,
+    {
+        if self.buffer.len() != self.width * self.height
+        {
+            self.initialize();
+        }
+        if self.width == 0
+        {
+            return;
+        }
+        let height = self.height;
+        let origin = self.origin;
+        let cell_size = self.cell_size;
+        let completed = AtomicUsize::new(0);
+
+        self.buffer.par_chunks_mut(self.width).enumerate().for_each(|(y, row)| {
+            for (x, value) in row.iter_mut().enumerate()
+            {
+                *value = source.sample([origin[0] + x as f64 * cell_size, origin[1] + y as f64 * cell_size]) as f32;
+            }
+            progress(completed.fetch_add(1, Ordering::Relaxed) + 1, height);
+        });
+    }
+    pub fn to_terrain_mesh(&self, settings: &TerrainSettings) -> Mesh
+    {
+        return build_terrain_mesh(self, settings).to_bevy_mesh();
+    }
+    pub fn normal_map(&self, settings: &NormalMapSettings) -> NormalMap
+    {
+        return NormalMap::from_height_map(self, settings);
+    }
+    // Runs droplet erosion on the map in place, see erosion::erode
+    pub fn erode(&mut self, settings: &ErosionSettings) -> ErosionMaps
+    {
+        return erode(self, settings);
+    }
+    pub fn statistics(&self) -> NoiseStatistics
+    {
+        return NoiseStatistics::from_buffer(&self.buffer);
+    }
+    // Linearly rescales the current min..max of the map onto min..max, a flat map becomes min everywhere
+    pub fn normalise(&mut self, min: f32, max: f32)
+    {
+        normalise_buffer(&mut self.buffer, min, max);
+    }
+    pub fn normalised(&self, min: f32, max: f32) -> NoiseMap2D
+    {
+        let mut map = self.clone();
+        map.normalise(min, max);
+        return map;
+    }
+    pub fn clamp(&mut self, min: f32, max: f32)
+    {
+        clamp_buffer(&mut self.buffer, min, max);
+    }
+    pub fn clamped(&self, min: f32, max: f32) -> NoiseMap2D
+    {
+        let mut map = self.clone();
+        map.clamp(min, max);
+        return map;
+    }
+    // Applies curve to every cell, e.g. |value| value * value to flatten valleys
+    pub fn remap<F>(&mut self, curve: F)
+    where
+        F: Fn(f32) -> f32 + Sync,
+    {
+        remap_buffer(&mut self.buffer, curve);
+    }
+    pub fn remapped<F>(&self, curve: F) -> NoiseMap2D
+    where
+        F: Fn(f32) -> f32 + Sync,
+    {
+        let mut map = self.clone();
+        map.remap(curve);
+        return map;
+    }
+
     pub fn to_image(&self) -> Image {
         let mut image = Image {
             data: Vec::with_capacity(self.buffer.len()),
@@ -245,7 +1063,7 @@ impl NoiseMap2D
 
         for &value in &self.buffer {
             
-            let pixel_value = (value * 255.0) as u8;
+            let pixel_value = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
             //println!("Assigning pixel value: {} with buffer value: {}", pixel_value, value);
             image.data.push(pixel_value);
             image.data.push(pixel_value);
@@ -256,58 +1074,56 @@ impl NoiseMap2D
         image
     }
     
-    pub fn save(&self, path: String) -> Result<bool, std::io::Error> //returns true if sucessfuly saved and false if not
+    // Writes a grayscale png, range decides which values map to black and white, anything outside it is clamped
+    pub fn save_png(&self, path: &str, bit_depth: PngBitDepth, range: ValueRange) -> Result<(), NoiseMapError>
     {
+        let (min, max) = range.resolve(&self.buffer);
 
-        if !Path::new(&path).extension().map_or(false, |ext| ext == "noise2d") {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file extension"));
-        }
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
 
-        //format of .noise2d file // 8 Bytes "width", 8 Bytes "height", each proceeding 4 Byte chunk is a f32 starting from the 0 position in the "buffer"
-        let mut file = File::create(path)?;
-        
-        // Write dimensions to the file
-        file.write_all(&(self.width as u64).to_ne_bytes())?;
-        file.write_all(&(self.height as u64).to_ne_bytes())?;        
+        let data: Vec<u8> = match bit_depth {
+            PngBitDepth::Eight => {
+                encoder.set_depth(png::BitDepth::Eight);
+                self.buffer.iter().map(|&value| (normalise_value(value, min, max) * 255.0).round() as u8).collect()
+            }
+            PngBitDepth::Sixteen => {
+                // png stores 16 bit samples big endian
+                encoder.set_depth(png::BitDepth::Sixteen);
+                self.buffer.iter().flat_map(|&value| ((normalise_value(value, min, max) * 65535.0).round() as u16).to_be_bytes()).collect()
+            }
+        };
 
-        // Write buffer data to the file
-        for &value in &self.buffer {
-            file.write_all(&value.to_ne_bytes())?;
-        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
 
-        Ok(true)
+        Ok(())
     }
     
-    pub fn load(path: &str) -> Result<NoiseMap2D, std::io::Error> {
-
-        if !Path::new(&path).extension().map_or(false, |ext| ext == "noise2d") {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file extension"));
-        }
-
-        let mut file = File::open(path)?;
+    pub fn save(&self, path: String) -> Result<(), NoiseMapError>
+    {
+        return self.save_with(path, &SaveOptions::default());
+    }
 
-        // Read dimensions from the file
-        let mut width_bytes = [0u8; 8];
-        let mut height_bytes = [0u8; 8];
-      
+    pub fn save_with(&self, path: String, options: &SaveOptions) -> Result<(), NoiseMapError>
+    {
+        //format of .noise2d file // versioned header (see noise_file.rs) with dimensions "width", "height", followed by little endian f32s starting from the 0 position in the "buffer", optionally deflate compressed
+        let grid = GridLayout { dimensions: &[self.width, self.height], origin: &self.origin, cell_size: self.cell_size };
+        return write_noise_file(&path, "noise2d", &grid, &self.buffer, self.metadata.as_ref(), options);
+    }
+    
+    pub fn load(path: &str) -> Result<NoiseMap2D, NoiseMapError> {
 
-        file.read_exact(&mut width_bytes)?;
-        file.read_exact(&mut height_bytes)?;
-        
+        let (header, buffer) = read_noise_file(path, "noise2d", 2)?;
 
-        let width = u64::from_ne_bytes(width_bytes) as usize;
-        let height = u64::from_ne_bytes(height_bytes) as usize;
-      
+        let width = header.dimensions[0] as usize;
+        let height = header.dimensions[1] as usize;
 
-        // Read buffer data from the file
-        let mut buffer = vec![0.0; width * height];
-        for value in &mut buffer {
-            let mut value_bytes = [0u8; 4];
-            file.read_exact(&mut value_bytes)?;
-            *value = f32::from_ne_bytes(value_bytes);
-        }
+        let origin = [header.origin[0], header.origin[1]];
 
-        Ok(NoiseMap2D { width, height, buffer })
+        Ok(NoiseMap2D { width, height, buffer, metadata: header.metadata, origin, cell_size: header.cell_size })
     }
 
 }
@@ -317,20 +1133,23 @@ impl NoiseMap2D
 
 
 //-------------------------------------------------------------------Defining Noise Map 3D-------------------------------------------------------------------//
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 pub struct NoiseMap3D
 {
     pub width: usize,
     pub height: usize,
     pub depth: usize,
-    pub buffer: Vec<f32>
+    pub buffer: Vec<f32>,
+    pub metadata: Option<NoiseMetadata>,
+    pub origin: [f64; 3],
+    pub cell_size: f64,
 }
 impl NoiseMap3D
 {
     pub fn from_values(width: usize, height: usize, depth: usize) -> NoiseMap3D
     {
         let buffer = vec![0.0 as f32; width * height * depth];                 
-        return NoiseMap3D{ width, height, depth, buffer};
+        return NoiseMap3D{ width, height, depth, buffer, metadata: None, origin: [0.0; 3], cell_size: 1.0 };
     }
 
     pub fn initialize(&mut self)
@@ -340,109 +1159,104 @@ impl NoiseMap3D
 
     pub fn get_value_at(&self, x: u32, y: u32, z: u32) -> f32
     {
-        return self.buffer[(z as usize * self.height * self.width) + (y as usize * self.height) + x as usize]
+        return self.buffer[(z as usize * self.height * self.width) + (y as usize * self.width) + x as usize]
     }
 
     pub fn set_value_at(&mut self, x: u32, y: u32, z: u32, value: f32)
     {   
-        self.buffer[(z as usize * self.height * self.width) + (y as usize * self.height) + x as usize] = value;
+        self.buffer[(z as usize * self.height * self.width) + (y as usize * self.width) + x as usize] = value;
     }
 
-    pub fn populate(&mut self, noise_generator: &CombinedNoiseGenerator3D)
+    pub fn world_position(&self, x: usize, y: usize, z: usize) -> [f64; 3]
     {
-        if self.buffer.is_empty()
-        {
-            self.initialize();
-        }
-        for z in 0..self.depth
-        {
-            for y in 0..self.height
-            {
-                for x in 0..self.width
-                {
-                   self.buffer[(z * self.height * self.width) + (y * self.height as usize) + x] = noise_generator.get_weighted_value(x as i32, y as i32, z  as i32);
-                }
-            }
-        }
+        return [self.origin[0] + x as f64 * self.cell_size, self.origin[1] + y as f64 * self.cell_size, self.origin[2] + z as f64 * self.cell_size];
+    }
 
+    // Samples the source once per cell at its world position, closures taking a [f64; 3] point work as sources too
+    pub fn populate_with<S>(&mut self, source: &S)
+    where
+        S: NoiseSource3D + Sync + ?Sized,
+    {
+        self.populate_with_progress(source, |_, _| {});
     }
-    pub fn populate_from_perlin(&mut self, perlin_generator: &NoiseGenerator3D<Perlin<3>>)
+    // Fills the map so its last z slice runs smoothly back into its first, for animations that play on repeat
+    pub fn populate_looping<S>(&mut self, source: &S, values: &NoiseValues)
+    where
+        S: NoiseSource4D + Sync + ?Sized,
     {
-        if self.buffer.is_empty()
-        {
-            self.initialize();
-        }
-        for z in 0..self.depth
-        {
-            for y in 0..self.height
-            {
-                for x in 0..self.width
-                {
-                   self.buffer[(z * self.height * self.width) + (y * self.height as usize) + x] = perlin_generator.get_value_at(x as i32, y as i32, z as i32);
-                }
-            }
-        }
+        let generator = LoopingNoiseGenerator3D {
+            source: |point: [f64; 4]| source.sample(point),
+            values: values.clone(),
+            period: self.depth as f64 * self.cell_size,
+        };
+        self.populate_with(&generator);
     }
-    pub fn populate_from_simplex(&mut self, simplex_generator: &NoiseGenerator3D<Simplex<3>>)
+    // progress is called with (completed z slices, total z slices) from whichever thread finished the slice
+    pub fn populate_with_progress<S, F>(&mut self, source: &S, progress: F)
+    where
+        S: NoiseSource3D + Sync + ?Sized,
+        F: Fn(usize, usize) + Sync,
     {
-        if self.buffer.is_empty()
-        {
-            self.initialize();
-        }
-        for z in 0..self.depth
-        {
-            for y in 0..self.height
-            {
-                for x in 0..self.width
-                {
-                   self.buffer[(z * self.height * self.width) + (y * self.height as usize) + x] = simplex_generator.get_value_at(x as i32, y as i32, z as i32);
-                }
-            }
-        }
+        let origin = self.origin;
+        let cell_size = self.cell_size;
+        self.fill_slices(|x, y, z| source.sample([origin[0] + x as f64 * cell_size, origin[1] + y as f64 * cell_size, origin[2] + z as f64 * cell_size]) as f32, progress);
     }
-    pub fn populate_from_worley(&mut self, worley_generator: &NoiseGenerator3D<Worley<3>>)
+
+    // Fills the buffer one z slice per task across all cores, every cell only depends on its own coordinates so the output matches a serial fill exactly
+    fn fill_slices<G, F>(&mut self, sample: G, progress: F)
+    where
+        G: Fn(usize, usize, usize) -> f32 + Sync,
+        F: Fn(usize, usize) + Sync,
     {
-        if self.buffer.is_empty()
+        if self.buffer.len() != self.width * self.height * self.depth
         {
             self.initialize();
         }
-        for z in 0..self.depth
+        let slice_size = self.width * self.height;
+        if slice_size == 0
         {
-            for y in 0..self.height
+            return;
+        }
+        let width = self.width;
+        let depth = self.depth;
+        let completed = AtomicUsize::new(0);
+
+        self.buffer.par_chunks_mut(slice_size).enumerate().for_each(|(z, slice)| {
+            for (index, value) in slice.iter_mut().enumerate()
             {
-                for x in 0..self.width
-                {
-                   self.buffer[(z * self.height * self.width) + (y * self.height as usize) + x] = worley_generator.get_value_at(x as i32, y as i32, z as i32);
-                }
+                *value = sample(index % width, index / width, z);
             }
-        }
+            progress(completed.fetch_add(1, Ordering::Relaxed) + 1, depth);
+        });
     }
 
     pub fn get_slice_x(&self, x_level: u32) -> NoiseMap2D
     {
-        let mut buffer = Vec::with_capacity(self.depth * self.height);
+        // The slice's rows run along y, one row per z level
+        let mut buffer = vec![0.0 as f32; self.depth * self.height];
         for z in 0..self.depth
         {
             for y in 0..self.height
             {
-                buffer[z * self.depth + y] = self.get_value_at(x_level, y as u32, z as u32);
+                buffer[z * self.height + y] = self.get_value_at(x_level, y as u32, z as u32);
             }
         }
 
-        return  NoiseMap2D{ width: self.height, height: self.depth, buffer };
+        return  NoiseMap2D{ width: self.height, height: self.depth, buffer, metadata: None, origin: [self.origin[1], self.origin[2]], cell_size: self.cell_size };
     }
     pub fn get_slice_y(&self, y_level: u32) -> NoiseMap2D
     {
-        let mut buffer = Vec::with_capacity(self.depth * self.height);
+        // The slice's rows run along x, one row per z level
+        let mut buffer = vec![0.0 as f32; self.depth * self.width];
         for z in 0..self.depth
         {
             for x in 0..self.width
             {
-                buffer[z * self.depth + x] = self.get_value_at(x as u32, y_level, z as u32);
+                buffer[z * self.width + x] = self.get_value_at(x as u32, y_level, z as u32);
             }
         }
 
-        return  NoiseMap2D{ width: self.height, height: self.depth, buffer };
+        return  NoiseMap2D{ width: self.width, height: self.depth, buffer, metadata: None, origin: [self.origin[0], self.origin[2]], cell_size: self.cell_size };
     }
     pub fn get_slice_z(&self, z_level: u32) -> NoiseMap2D
     {
@@ -453,14 +1267,61 @@ impl NoiseMap3D
             for x in 0..self.width
             {
                 //println!("Assigning pixel x:{}, y:{} . Value: {}",x,y, self.get_value_at(x as u32, y as u32, z_level));
-                temp_buffer[y * self.height + x] = self.get_value_at(x as u32, y as u32, z_level)
+                temp_buffer[y * self.width + x] = self.get_value_at(x as u32, y as u32, z_level)
             }
         }
 
-        return  NoiseMap2D{ width: self.width, height: self.height, buffer: temp_buffer };
+        return  NoiseMap2D{ width: self.width, height: self.height, buffer: temp_buffer, metadata: None, origin: [self.origin[0], self.origin[1]], cell_size: self.cell_size };
     }
 
 
+    // Triangle mesh of the surface where the map crosses threshold, anything above it counts as solid
+    pub fn extract_isosurface(&self, threshold: f32) -> MeshData
+    {
+        return extract_isosurface(self, threshold);
+    }
+
+    pub fn statistics(&self) -> NoiseStatistics
+    {
+        return NoiseStatistics::from_buffer(&self.buffer);
+    }
+    // Linearly rescales the current min..max of the map onto min..max, a flat map becomes min everywhere
+    pub fn normalise(&mut self, min: f32, max: f32)
+    {
+        normalise_buffer(&mut self.buffer, min, max);
+    }
+    pub fn normalised(&self, min: f32, max: f32) -> NoiseMap3D
+    {
+        let mut map = self.clone();
+        map.normalise(min, max);
+        return map;
+    }
+    pub fn clamp(&mut self, min: f32, max: f32)
+    {
+        clamp_buffer(&mut self.buffer, min, max);
+    }
+    pub fn clamped(&self, min: f32, max: f32) -> NoiseMap3D
+    {
+        let mut map = self.clone();
+        map.clamp(min, max);
+        return map;
+    }
+    // Applies curve to every cell, e.g. |value| value * value to flatten valleys
+    pub fn remap<F>(&mut self, curve: F)
+    where
+        F: Fn(f32) -> f32 + Sync,
+    {
+        remap_buffer(&mut self.buffer, curve);
+    }
+    pub fn remapped<F>(&self, curve: F) -> NoiseMap3D
+    where
+        F: Fn(f32) -> f32 + Sync,
+    {
+        let mut map = self.clone();
+        map.remap(curve);
+        return map;
+    }
+
     pub fn to_image(&self) -> Image {
         let mut image = Image {
             data: Vec::with_capacity(self.buffer.len()),
@@ -483,7 +1344,7 @@ impl NoiseMap3D
         };
 
         for &value in &self.buffer {
-            let pixel_value = (value * 255.0).round() as u8;
+            let pixel_value = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
             image.data.push(pixel_value);
             image.data.push(pixel_value);
             image.data.push(pixel_value);
@@ -493,60 +1354,373 @@ impl NoiseMap3D
         image
     }
 
-    pub fn save(&self, path: String) -> Result<bool, std::io::Error> //returns true if sucessfuly saved and false if not
+    pub fn save(&self, path: String) -> Result<(), NoiseMapError>
+    {
+        return self.save_with(path, &SaveOptions::default());
+    }
+
+    pub fn save_with(&self, path: String, options: &SaveOptions) -> Result<(), NoiseMapError>
     {
-        if !Path::new(&path).extension().map_or(false, |ext| ext == "noise3d") {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file extension"));
+        //format of .noise3d file // versioned header (see noise_file.rs) with dimensions "width", "height", "depth", followed by little endian f32s starting from the 0 position in the "buffer", optionally deflate compressed per z slice
+        let grid = GridLayout { dimensions: &[self.width, self.height, self.depth], origin: &self.origin, cell_size: self.cell_size };
+        return write_noise_file(&path, "noise3d", &grid, &self.buffer, self.metadata.as_ref(), options);
+    }
+
+    pub fn load(path: String) -> Result<NoiseMap3D, NoiseMapError> {
+
+        let (header, buffer) = read_noise_file(&path, "noise3d", 3)?;
+
+        let width = header.dimensions[0] as usize;
+        let height = header.dimensions[1] as usize;
+        let depth = header.dimensions[2] as usize;
+
+        let origin = [header.origin[0], header.origin[1], header.origin[2]];
+
+        Ok(NoiseMap3D { width, height, depth, buffer, metadata: header.metadata, origin, cell_size: header.cell_size })
+    }
+    
+}
+
+
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn temp_path(name: &str) -> String
+    {
+        return std::env::temp_dir().join(format!("noise_project_noise_{}_{}", std::process::id(), name)).to_str().unwrap().to_string();
+    }
+
+    // Negative points should behave like every other point, so they have to match the point one lattice period further along
+    fn assert_matches_shifted<F>(sample: F, points: &[[f64; 2]])
+    where
+        F: Fn([f64; 2]) -> f64,
+    {
+        for &[x, y] in points {
+            let (value, shifted) = (sample([x, y]), sample([x + 256.0, y + 256.0]));
+            assert!((value - shifted).abs() < 1e-6, "{:?}: {} != {}", [x, y], value, shifted);
         }
+    }
 
-        //format of .noise3d file // 8 Bytes "width", 8 Bytes "height", 8 Bytes "depth", each proceeding 4 Byte chunk is a f32 starting from the 0 position in the "buffer"
-        let mut file = File::create(path)?;
-        
-        // Write dimensions to the file
-        file.write_all(&(self.width as u64).to_ne_bytes())?;
-        file.write_all(&(self.height as u64).to_ne_bytes())?;
-        file.write_all(&(self.depth as u64).to_ne_bytes())?;
+    #[test]
+    fn sources_wrap_negative_points()
+    {
+        let points: Vec<[f64; 2]> = (0..200).map(|index| [-40.0 + index as f64 * 0.37, -25.0 + index as f64 * 0.21]).collect();
+        let perlin = Perlin::<2>::new(3);
+        let worley = Worley::<2>::new(3);
+        assert_matches_shifted(|point| NoiseSource2D::sample(&perlin, point), &points);
+        assert_matches_shifted(|point| NoiseSource2D::sample(&worley, point), &points);
+        assert_ne!(NoiseSource2D::sample(&perlin, [-3.3, 0.5]), NoiseSource2D::sample(&perlin, [-7.3, 0.5]));
+
+        // Points that were already non-negative keep their value
+        assert_eq!(NoiseSource2D::sample(&perlin, [0.7, 12.25]), libnoise::Generator::sample(&perlin, [0.7, 12.25]));
+        assert_eq!(NoiseSource3D::sample(&Perlin::<3>::new(3), [300.5, 0.25, 9.0]), libnoise::Generator::sample(&Perlin::<3>::new(3), [300.5, 0.25, 9.0]));
+    }
 
-        // Write buffer data to the file
-        for &value in &self.buffer {
-            file.write_all(&value.to_ne_bytes())?;
+    #[test]
+    fn domain_warp_wraps_negative_points()
+    {
+        // Strong enough to push every point left of about x = 8 into negative coordinates
+        let warp = DomainWarp2D { source: Perlin::<2>::new(3), warpers: vec![Perlin::<2>::new(4)], strength: 8.0, iterations: 2 };
+        let points: Vec<[f64; 2]> = (0..200).map(|index| [-20.0 + index as f64 * 0.13, 0.5 + index as f64 * 0.05]).collect();
+        assert_matches_shifted(|point| warp.sample(point), &points);
+    }
+
+    fn fbm_values() -> NoiseValues
+    {
+        return NoiseValues { octaves: 3, scale: 0.25, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+    }
+
+    // Tiles left of and above a negative origin have to line up with their neighbours exactly like tiles on the positive side do
+    #[test]
+    fn tiles_around_negative_origins_line_up()
+    {
+        let generator = NoiseGenerator2D { source: Perlin::<2>::new(9), values: fbm_values() };
+        let mut whole = NoiseMap2D::from_values(32, 16);
+        whole.origin = [-16.0, -8.0];
+        whole.populate_with(&generator);
+
+        for (tile_x, tile_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let mut tile = NoiseMap2D::from_values(16, 8);
+            tile.origin = whole.world_position(tile_x * 16, tile_y * 8);
+            tile.populate_with(&generator);
+            for y in 0..8 {
+                for x in 0..16 {
+                    assert_eq!(tile.get_value_at(x, y), whole.get_value_at(tile_x as u32 * 16 + x, tile_y as u32 * 8 + y));
+                }
+            }
         }
 
-        Ok(true)
+        // 1024 cells is a whole number of lattice periods at every octave, so a negative tile has to repeat the positive one that far along
+        let mut shifted = NoiseMap2D::from_values(32, 16);
+        shifted.origin = [-16.0 + 1024.0, -8.0 + 1024.0];
+        shifted.populate_with(&generator);
+        assert!(whole.buffer.iter().zip(&shifted.buffer).all(|(a, b)| (a - b).abs() < 1e-5));
+
+        let generator = NoiseGenerator3D { source: Worley::<3>::new(9), values: fbm_values() };
+        let mut map = NoiseMap3D::from_values(8, 8, 4);
+        map.origin = [-6.0, -4.0, -2.0];
+        map.populate_with(&generator);
+        let mut shifted = NoiseMap3D::from_values(8, 8, 4);
+        shifted.origin = [-6.0 + 1024.0, -4.0 + 1024.0, -2.0];
+        shifted.populate_with(&generator);
+        assert!(map.buffer.iter().zip(&shifted.buffer).all(|(a, b)| (a - b).abs() < 1e-4));
     }
 
-    pub fn load(path: String) -> Result<NoiseMap3D, std::io::Error> {
+    #[test]
+    fn slices_match_the_cells_they_cut_through()
+    {
+        let mut map = NoiseMap3D::from_values(5, 3, 4);
+        map.populate_with(&|point: [f64; 3]| point[0] + 10.0 * point[1] + 100.0 * point[2]);
+
+        let slice = map.get_slice_x(2);
+        assert_eq!((slice.width, slice.height), (3, 4));
+        let slice = map.get_slice_y(1);
+        assert_eq!((slice.width, slice.height), (5, 4));
+        for z in 0..4 {
+            for y in 0..3 {
+                assert_eq!(map.get_slice_x(2).get_value_at(y, z), map.get_value_at(2, y, z));
+            }
+            for x in 0..5 {
+                assert_eq!(map.get_slice_y(1).get_value_at(x, z), map.get_value_at(x, 1, z));
+            }
+        }
+    }
 
-        if !Path::new(&path).extension().map_or(false, |ext| ext == "noise3d") {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file extension"));
+    // Rows are width long, so a cell's index is y * width + x whatever the aspect ratio
+    #[test]
+    fn non_square_layout_round_trip()
+    {
+        let mut map = NoiseMap2D::from_values(7, 3);
+        map.populate_with(&|point: [f64; 2]| point[0] + 100.0 * point[1]);
+        for y in 0..3 {
+            for x in 0..7 {
+                assert_eq!(map.get_value_at(x, y), (x + 100 * y) as f32);
+                assert_eq!(map.buffer[(y * 7 + x) as usize], (x + 100 * y) as f32);
+            }
+        }
+        map.set_value_at(6, 2, -1.0);
+        assert_eq!(map.buffer[2 * 7 + 6], -1.0);
+
+        let path = temp_path("non_square.noise2d");
+        map.save(path.clone()).unwrap();
+        let loaded = NoiseMap2D::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.width, loaded.height), (7, 3));
+        assert_eq!(loaded.buffer, map.buffer);
+
+        let mut map = NoiseMap3D::from_values(5, 3, 2);
+        map.populate_with(&|point: [f64; 3]| point[0] + 10.0 * point[1] + 100.0 * point[2]);
+        for z in 0..2 {
+            for y in 0..3 {
+                for x in 0..5 {
+                    assert_eq!(map.get_value_at(x, y, z), (x + 10 * y + 100 * z) as f32);
+                    assert_eq!(map.buffer[(z * 15 + y * 5 + x) as usize], (x + 10 * y + 100 * z) as f32);
+                }
+            }
         }
 
-        let mut file = File::open(path)?;
+        let path = temp_path("non_square.noise3d");
+        map.save(path.clone()).unwrap();
+        let loaded = NoiseMap3D::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.depth), (5, 3, 2));
+        assert_eq!(loaded.buffer, map.buffer);
+    }
 
-        // Read dimensions from the file
-        let mut width_bytes = [0u8; 8];
-        let mut height_bytes = [0u8; 8];
-        let mut depth_bytes = [0u8; 8];
+    #[test]
+    fn parallel_fill_matches_serial_fill()
+    {
+        let generator = NoiseGenerator3D { source: Simplex::<3>::new(5), values: fbm_values() };
+        let mut map = NoiseMap3D::from_values(9, 5, 7);
+        let reports = std::sync::Mutex::new(Vec::new());
+        map.populate_with_progress(&generator, |completed, total| reports.lock().unwrap().push((completed, total)));
+
+        for z in 0..7 {
+            for y in 0..5 {
+                for x in 0..9 {
+                    assert_eq!(map.get_value_at(x, y, z), generator.get_value_at(x as i32, y as i32, z as i32));
+                }
+            }
+        }
 
-        file.read_exact(&mut width_bytes)?;
-        file.read_exact(&mut height_bytes)?;
-        file.read_exact(&mut depth_bytes)?;
+        // One report per z slice, whichever thread gets there, ending at every slice done
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort();
+        assert_eq!(reports, (1..=7).map(|completed| (completed, 7)).collect::<Vec<_>>());
+        let (completed, total) = reports[reports.len() - 1];
+        assert_eq!(completed as f32 / total as f32, 1.0);
+    }
 
-        let width = u64::from_ne_bytes(width_bytes) as usize;
-        let height = u64::from_ne_bytes(height_bytes) as usize;
-        let depth = u64::from_ne_bytes(depth_bytes) as usize;
+    // Decoded samples of a grayscale png, 16 bit ones joined back up from their big endian bytes
+    fn read_png(path: &str) -> (u32, u32, Vec<u32>)
+    {
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0u8; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut data).unwrap();
+        assert_eq!(frame.color_type, png::ColorType::Grayscale);
+        let data = &data[..frame.buffer_size()];
+        let samples = match frame.bit_depth {
+            png::BitDepth::Sixteen => data.chunks_exact(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as u32).collect(),
+            _ => data.iter().map(|&byte| byte as u32).collect(),
+        };
+        return (frame.width, frame.height, samples);
+    }
 
-        // Read buffer data from the file
-        let mut buffer = vec![0.0; width * height * depth];
-        for value in &mut buffer {
-            let mut value_bytes = [0u8; 4];
-            file.read_exact(&mut value_bytes)?;
-            *value = f32::from_ne_bytes(value_bytes);
+    #[test]
+    fn png_export_maps_the_value_range_onto_black_and_white()
+    {
+        let mut map = NoiseMap2D::from_values(3, 2);
+        map.buffer = vec![-2.0, -1.0, 0.0, 0.5, 1.0, 3.0];
+
+        let cases = [
+            (PngBitDepth::Eight, ValueRange::Fixed { min: -1.0, max: 1.0 }, vec![0, 0, 128, 191, 255, 255]),
+            (PngBitDepth::Sixteen, ValueRange::Fixed { min: -1.0, max: 1.0 }, vec![0, 0, 32768, 49151, 65535, 65535]),
+            (PngBitDepth::Eight, ValueRange::Auto, vec![0, 51, 102, 128, 153, 255]),
+            (PngBitDepth::Sixteen, ValueRange::Auto, vec![0, 13107, 26214, 32768, 39321, 65535]),
+        ];
+        for (bit_depth, range, expected) in cases {
+            let path = temp_path("export.png");
+            map.save_png(&path, bit_depth, range).unwrap();
+            let (width, height, samples) = read_png(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!((width, height), (3, 2));
+            assert_eq!(samples, expected, "{:?} {:?}", bit_depth, range);
         }
+    }
 
-        Ok(NoiseMap3D { width, height, depth, buffer })
+    #[test]
+    fn statistics_and_range_operations()
+    {
+        let mut map = NoiseMap2D::from_values(4, 2);
+        map.buffer = vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let statistics = map.statistics();
+        assert_eq!((statistics.min, statistics.max, statistics.mean, statistics.std_dev), (2.0, 9.0, 5.0, 2.0));
+        assert_eq!(NoiseStatistics::from_buffer(&[]), NoiseStatistics { min: 0.0, max: 0.0, mean: 0.0, std_dev: 0.0 });
+
+        let normalised = map.normalised(-1.0, 1.0);
+        assert_eq!((normalised.statistics().min, normalised.statistics().max), (-1.0, 1.0));
+        assert!((normalised.get_value_at(0, 1) - (-1.0 + 2.0 * 3.0 / 7.0)).abs() < 1e-6);
+        assert_eq!(map.clamped(3.0, 6.0).buffer, vec![3.0, 4.0, 4.0, 4.0, 5.0, 5.0, 6.0, 6.0]);
+        assert_eq!(map.remapped(|value| value * value).buffer, vec![4.0, 16.0, 16.0, 16.0, 25.0, 25.0, 49.0, 81.0]);
+
+        // A flat map has no range to stretch, it lands on min rather than dividing by zero
+        let mut flat = NoiseMap3D::from_values(3, 2, 2);
+        flat.buffer.iter_mut().for_each(|value| *value = 0.75);
+        flat.normalise(0.0, 1.0);
+        assert!(flat.buffer.iter().all(|&value| value == 0.0));
+        let statistics = flat.statistics();
+        assert!(!statistics.mean.is_nan() && !statistics.std_dev.is_nan());
+    }
+
+    #[test]
+    fn fractal_modes_stay_in_range_and_differ_from_fbm()
+    {
+        let sample_mode = |fractal_mode| {
+            let values = NoiseValues { octaves: 4, scale: 0.05, persistence: 0.5, lacunarity: 2.0, fractal_mode };
+            let generator = NoiseGenerator2D { source: Perlin::<2>::new(11), values };
+            let mut map = NoiseMap2D::from_values(48, 48);
+            map.populate_with(&generator);
+            map.buffer
+        };
+        // Raw source values stay within -1..1, so each octave adds at most its amplitude
+        let amplitude_sum = 1.0 + 0.5 + 0.25 + 0.125;
+        let fbm = sample_mode(FractalMode::Fbm);
+        assert!(fbm.iter().all(|value| value.abs() <= amplitude_sum));
+
+        let modes = [
+            (FractalMode::Ridged { offset: 1.0, gain: 2.0 }, 0.0, amplitude_sum),
+            (FractalMode::Billow, -amplitude_sum, amplitude_sum),
+            (FractalMode::Turbulence, 0.0, amplitude_sum),
+            (FractalMode::HybridMulti { offset: 0.7, gain: 1.0 }, -1.7 * amplitude_sum, 1.7 * amplitude_sum),
+        ];
+        for (mode, min, max) in modes {
+            let buffer = sample_mode(mode);
+            let statistics = NoiseStatistics::from_buffer(&buffer);
+            assert!(statistics.min >= min && statistics.max <= max, "{:?} gave {}..{}", mode, statistics.min, statistics.max);
+            assert!(statistics.max > statistics.min, "{:?} is flat", mode);
+            let difference = buffer.iter().zip(&fbm).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+            assert!(difference > 0.1, "{:?} is too close to fbm", mode);
+        }
+
+        // Folding the signal over makes every billow / turbulence octave depend only on |noise|
+        let turbulence = sample_mode(FractalMode::Turbulence);
+        let billow = sample_mode(FractalMode::Billow);
+        for (turbulence, billow) in turbulence.iter().zip(&billow) {
+            assert!((billow - (2.0 * turbulence - amplitude_sum)).abs() < 1e-5);
+        }
     }
-    
-}
 
+    // Largest step between neighbouring cells across the wrap, against the largest step anywhere inside the map
+    fn seam_and_inside_steps<F>(count: usize, lines: usize, value: F) -> (f32, f32)
+    where
+        F: Fn(usize, usize) -> f32,
+    {
+        let mut seam: f32 = 0.0;
+        let mut inside: f32 = 0.0;
+        for line in 0..lines {
+            seam = seam.max((value(0, line) - value(count - 1, line)).abs());
+            for index in 1..count {
+                inside = inside.max((value(index, line) - value(index - 1, line)).abs());
+            }
+        }
+        return (seam, inside);
+    }
 
+    #[test]
+    fn tileable_maps_wrap_around()
+    {
+        let source = Perlin::<4>::new(2);
+        let values = NoiseValues { octaves: 3, scale: 0.1, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+        let mut map = NoiseMap2D::from_values(32, 24);
+        map.populate_tileable(&source, &values);
+
+        // Column 0 follows column width - 1 and row 0 follows row height - 1 like any other neighbours
+        let (seam, inside) = seam_and_inside_steps(32, 24, |x, y| map.get_value_at(x as u32, y as u32));
+        assert!(seam <= inside, "column seam step {} against {} inside the map", seam, inside);
+        let (seam, inside) = seam_and_inside_steps(24, 32, |y, x| map.get_value_at(x as u32, y as u32));
+        assert!(seam <= inside, "row seam step {} against {} inside the map", seam, inside);
+
+        // One whole tile further along is the same tile
+        let generator = TileableNoiseGenerator2D { source: Perlin::<4>::new(2), values, period: [32.0, 24.0] };
+        for y in 0..24 {
+            for x in 0..32 {
+                let value = map.get_value_at(x, y);
+                assert_eq!(generator.get_value_at(x as i32, y as i32), value);
+                assert!((generator.get_value_at(x as i32 + 32, y as i32) - value).abs() < 1e-5);
+                assert!((generator.get_value_at(x as i32, y as i32 - 24) - value).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn looping_maps_wrap_around_in_z()
+    {
+        let values = NoiseValues { octaves: 3, scale: 0.1, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+        let mut map = NoiseMap3D::from_values(8, 6, 16);
+        map.cell_size = 0.5;
+        map.populate_looping(&Simplex::<4>::new(6), &values);
+
+        // Slice depth - 1 leads into slice 0 like any other pair of neighbouring slices
+        let cell = |z: usize, line: usize| map.get_value_at((line % 8) as u32, (line / 8) as u32, z as u32);
+        let (seam, inside) = seam_and_inside_steps(16, 8 * 6, cell);
+        assert!(seam <= inside, "z seam step {} against {} inside the map", seam, inside);
+
+        let generator = LoopingNoiseGenerator3D { source: Simplex::<4>::new(6), values, period: 16.0 * 0.5 };
+        for z in 0..16 {
+            for y in 0..6 {
+                for x in 0..8 {
+                    let point = map.world_position(x, y, z);
+                    assert_eq!(generator.get_value_at_point(point), map.get_value_at(x as u32, y as u32, z as u32));
+                    let value = generator.get_value_at_point(point);
+                    let looped = generator.get_value_at_point([point[0], point[1], point[2] + 16.0 * 0.5]);
+                    assert!((value - looped).abs() < 1e-5, "{:?}: {} != {}", point, value, looped);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::{fmt, fs, io, path::Path};
+
+use libnoise::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::noise::*;
+
+//-------------------------------------------------------------------Defining Generator Config-------------------------------------------------------------------//
+// Serialisable description of a generator, loaded from .ron, .json or .toml presets and turned into a live source with build_2d / build_3d
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind
+{
+    Perlin,
+    Simplex,
+    Worley,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SourceConfig
+{
+    pub kind: SourceKind,
+    #[serde(with = "seed_format")]
+    pub seed: u64,
+    pub values: NoiseValues,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Combiner
+{
+    WeightedAverage,
+    Sum,
+    Multiply,
+    Min,
+    Max,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WeightedConfig
+{
+    pub weight: f32,
+    pub generator: GeneratorConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GeneratorConfig
+{
+    Source(SourceConfig),
+    Combined { combiner: Combiner, inputs: Vec<WeightedConfig> },
+    DomainWarp { source: Box<GeneratorConfig>, warpers: Vec<GeneratorConfig>, strength: f64, iterations: usize },
+}
+
+pub type BoxedNoiseSource2D = Box<dyn NoiseSource2D + Send + Sync>;
+pub type BoxedNoiseSource3D = Box<dyn NoiseSource3D + Send + Sync>;
+pub type BoxedNoiseSource4D = Box<dyn NoiseSource4D + Send + Sync>;
+
+impl SourceConfig
+{
+    // The bare 4D source without its octaves, for the tileable and looping generators which apply values themselves
+    pub fn build_source_4d(&self) -> BoxedNoiseSource4D
+    {
+        match self.kind {
+            SourceKind::Perlin => Box::new(Perlin::<4>::new(self.seed)),
+            SourceKind::Simplex => Box::new(Simplex::<4>::new(self.seed)),
+            SourceKind::Worley => Box::new(Worley::<4>::new(self.seed)),
+        }
+    }
+}
+
+impl GeneratorConfig
+{
+    pub fn build_2d(&self) -> BoxedNoiseSource2D
+    {
+        match self {
+            GeneratorConfig::Source(source) => {
+                let values = source.values.clone();
+                match source.kind {
+                    SourceKind::Perlin => Box::new(NoiseGenerator2D { source: Perlin::<2>::new(source.seed), values }),
+                    SourceKind::Simplex => Box::new(NoiseGenerator2D { source: Simplex::<2>::new(source.seed), values }),
+                    SourceKind::Worley => Box::new(NoiseGenerator2D { source: Worley::<2>::new(source.seed), values }),
+                }
+            }
+            GeneratorConfig::Combined { combiner, inputs } => Box::new(CombinedSource {
+                combiner: *combiner,
+                inputs: inputs.iter().map(|input| (input.generator.build_2d(), input.weight)).collect(),
+            }),
+            GeneratorConfig::DomainWarp { source, warpers, strength, iterations } => Box::new(DomainWarp2D {
+                source: source.build_2d(),
+                warpers: warpers.iter().map(|warper| warper.build_2d()).collect(),
+                strength: *strength,
+                iterations: *iterations,
+            }),
+        }
+    }
+
+    pub fn build_3d(&self) -> BoxedNoiseSource3D
+    {
+        match self {
+            GeneratorConfig::Source(source) => {
+                let values = source.values.clone();
+                match source.kind {
+                    SourceKind::Perlin => Box::new(NoiseGenerator3D { source: Perlin::<3>::new(source.seed), values }),
+                    SourceKind::Simplex => Box::new(NoiseGenerator3D { source: Simplex::<3>::new(source.seed), values }),
+                    SourceKind::Worley => Box::new(NoiseGenerator3D { source: Worley::<3>::new(source.seed), values }),
+                }
+            }
+            GeneratorConfig::Combined { combiner, inputs } => Box::new(CombinedSource {
+                combiner: *combiner,
+                inputs: inputs.iter().map(|input| (input.generator.build_3d(), input.weight)).collect(),
+            }),
+            GeneratorConfig::DomainWarp { source, warpers, strength, iterations } => Box::new(DomainWarp3D {
+                source: source.build_3d(),
+                warpers: warpers.iter().map(|warper| warper.build_3d()).collect(),
+                strength: *strength,
+                iterations: *iterations,
+            }),
+        }
+    }
+
+    // Seed of the first source in the tree, used to label saved maps
+    pub fn seed(&self) -> Option<u64>
+    {
+        match self {
+            GeneratorConfig::Source(source) => Some(source.seed),
+            GeneratorConfig::Combined { inputs, .. } => inputs.iter().find_map(|input| input.generator.seed()),
+            GeneratorConfig::DomainWarp { source, .. } => source.seed(),
+        }
+    }
+
+    // The format is picked from the extension: .ron, .json or .toml
+    pub fn load(path: &str) -> Result<GeneratorConfig, ConfigError>
+    {
+        let text = fs::read_to_string(path)?;
+        let preset: PresetFile = match ConfigFormat::from_path(path)? {
+            ConfigFormat::Ron => ron::from_str(&text)?,
+            ConfigFormat::Json => serde_json::from_str(&text)?,
+            ConfigFormat::Toml => toml::from_str(&text)?,
+        };
+        Ok(preset.generator)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ConfigError>
+    {
+        let preset = PresetFile { generator: self.clone() };
+        let text = match ConfigFormat::from_path(path)? {
+            ConfigFormat::Ron => ron::ser::to_string_pretty(&preset, ron::ser::PrettyConfig::default())?,
+            ConfigFormat::Json => serde_json::to_string_pretty(&preset)?,
+            ConfigFormat::Toml => toml::to_string_pretty(&preset)?,
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+// toml needs a table at the root, so every format wraps the generator in one
+#[derive(Serialize, Deserialize)]
+struct PresetFile
+{
+    generator: GeneratorConfig,
+}
+
+// toml integers are i64, so seeds above i64::MAX are written as strings instead. Either form is read back in every format
+mod seed_format
+{
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredSeed
+    {
+        Number(u64),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        if *seed <= i64::MAX as u64 {
+            return serializer.serialize_u64(*seed);
+        }
+        return serializer.serialize_str(&seed.to_string());
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error>
+    {
+        match StoredSeed::deserialize(deserializer)? {
+            StoredSeed::Number(seed) => Ok(seed),
+            StoredSeed::Text(text) => text.parse().map_err(|_| de::Error::custom(format!("seed {:?} is not a whole number from 0 to {}", text, u64::MAX))),
+        }
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Combined Source-------------------------------------------------------------------//
+// Live form of GeneratorConfig::Combined, every input is sampled at the same point and folded with the combiner
+pub struct CombinedSource<S>
+where
+    S: ?Sized,
+{
+    pub combiner: Combiner,
+    pub inputs: Vec<(Box<S>, f32)>,
+}
+impl<S> CombinedSource<S>
+where
+    S: ?Sized,
+{
+    fn combine<F>(&self, mut sample: F) -> f64
+    where
+        F: FnMut(&S) -> f64,
+    {
+        let values = self.inputs.iter().map(|(input, weight)| (sample(input), *weight as f64));
+        match self.combiner {
+            Combiner::WeightedAverage => {
+                let (sum, total_weight) = values.fold((0.0, 0.0), |(sum, total), (value, weight)| (sum + value * weight, total + weight));
+                if total_weight == 0.0 { 0.0 } else { sum / total_weight }
+            }
+            Combiner::Sum => values.map(|(value, weight)| value * weight).sum(),
+            Combiner::Multiply => values.map(|(value, weight)| value * weight).product(),
+            Combiner::Min => values.map(|(value, weight)| value * weight).reduce(f64::min).unwrap_or(0.0),
+            Combiner::Max => values.map(|(value, weight)| value * weight).reduce(f64::max).unwrap_or(0.0),
+        }
+    }
+
+    // Same fold as combine, carrying each input's gradient along
+    fn combine_with_gradient<F, const N: usize>(&self, mut sample: F) -> (f64, [f64; N])
+    where
+        F: FnMut(&S) -> (f64, [f64; N]),
+    {
+        let values = self.inputs.iter().map(|(input, weight)| {
+            let (value, gradient) = sample(input);
+            let weight = *weight as f64;
+            (value * weight, gradient.map(|slope| slope * weight), weight)
+        });
+        match self.combiner {
+            Combiner::WeightedAverage => {
+                let (sum, sum_gradient, total_weight) = values.fold((0.0, [0.0; N], 0.0), |(sum, sum_gradient, total), (value, gradient, weight)| {
+                    (sum + value, std::array::from_fn(|axis| sum_gradient[axis] + gradient[axis]), total + weight)
+                });
+                if total_weight == 0.0 { (0.0, [0.0; N]) } else { (sum / total_weight, sum_gradient.map(|slope| slope / total_weight)) }
+            }
+            Combiner::Sum => values.fold((0.0, [0.0; N]), |(sum, sum_gradient), (value, gradient, _)| {
+                (sum + value, std::array::from_fn(|axis| sum_gradient[axis] + gradient[axis]))
+            }),
+            // Product rule, one input at a time
+            Combiner::Multiply => values.fold((1.0, [0.0; N]), |(product, product_gradient), (value, gradient, _)| {
+                (product * value, std::array::from_fn(|axis| product_gradient[axis] * value + product * gradient[axis]))
+            }),
+            // The gradient of whichever input is picked
+            Combiner::Min => values.map(|(value, gradient, _)| (value, gradient)).reduce(|best, next| if next.0 < best.0 { next } else { best }).unwrap_or((0.0, [0.0; N])),
+            Combiner::Max => values.map(|(value, gradient, _)| (value, gradient)).reduce(|best, next| if next.0 > best.0 { next } else { best }).unwrap_or((0.0, [0.0; N])),
+        }
+    }
+}
+
+impl NoiseSource2D for CombinedSource<dyn NoiseSource2D + Send + Sync> {
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        return self.combine(|input| input.sample(point));
+    }
+    fn sample_with_gradient(&self, point: [f64; 2]) -> (f64, [f64; 2]) {
+        return self.combine_with_gradient(|input| input.sample_with_gradient(point));
+    }
+}
+
+impl NoiseSource3D for CombinedSource<dyn NoiseSource3D + Send + Sync> {
+    fn sample(&self, point: [f64; 3]) -> f64 {
+        return self.combine(|input| input.sample(point));
+    }
+    fn sample_with_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) {
+        return self.combine_with_gradient(|input| input.sample_with_gradient(point));
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Config Format-------------------------------------------------------------------//
+enum ConfigFormat
+{
+    Ron,
+    Json,
+    Toml,
+}
+impl ConfigFormat
+{
+    fn from_path(path: &str) -> Result<ConfigFormat, ConfigError>
+    {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Ok(ConfigFormat::Ron),
+            Some("json") => Ok(ConfigFormat::Json),
+            Some("toml") => Ok(ConfigFormat::Toml),
+            _ => Err(ConfigError::UnknownFormat(path.to_string())),
+        }
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Config Error-------------------------------------------------------------------//
+#[derive(Debug)]
+pub enum ConfigError
+{
+    UnknownFormat(String),
+    RonDeserialize(ron::error::SpannedError),
+    RonSerialize(ron::Error),
+    Json(serde_json::Error),
+    TomlDeserialize(toml::de::Error),
+    TomlSerialize(toml::ser::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ConfigError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            ConfigError::UnknownFormat(path) => write!(f, "{} is not a .ron, .json or .toml file", path),
+            ConfigError::RonDeserialize(error) => write!(f, "invalid ron: {}", error),
+            ConfigError::RonSerialize(error) => write!(f, "failed to write ron: {}", error),
+            ConfigError::Json(error) => write!(f, "invalid json: {}", error),
+            ConfigError::TomlDeserialize(error) => write!(f, "invalid toml: {}", error),
+            ConfigError::TomlSerialize(error) => write!(f, "failed to write toml: {}", error),
+            ConfigError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ron::error::SpannedError> for ConfigError
+{
+    fn from(error: ron::error::SpannedError) -> ConfigError
+    {
+        ConfigError::RonDeserialize(error)
+    }
+}
+
+impl From<ron::Error> for ConfigError
+{
+    fn from(error: ron::Error) -> ConfigError
+    {
+        ConfigError::RonSerialize(error)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError
+{
+    fn from(error: serde_json::Error) -> ConfigError
+    {
+        ConfigError::Json(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError
+{
+    fn from(error: toml::de::Error) -> ConfigError
+    {
+        ConfigError::TomlDeserialize(error)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError
+{
+    fn from(error: toml::ser::Error) -> ConfigError
+    {
+        ConfigError::TomlSerialize(error)
+    }
+}
+
+impl From<io::Error> for ConfigError
+{
+    fn from(error: io::Error) -> ConfigError
+    {
+        ConfigError::Io(error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn temp_path(name: &str) -> String
+    {
+        return std::env::temp_dir().join(format!("noise_project_config_{}_{}", std::process::id(), name)).to_str().unwrap().to_string();
+    }
+
+    #[test]
+    fn seeds_round_trip_in_every_format()
+    {
+        let values = NoiseValues { octaves: 3, scale: 0.05, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm };
+        for seed in [0, 42, i64::MAX as u64, i64::MAX as u64 + 1, u64::MAX] {
+            let config = GeneratorConfig::Source(SourceConfig { kind: SourceKind::Perlin, seed, values: values.clone() });
+            for extension in ["ron", "json", "toml"] {
+                let path = temp_path(&format!("seed_{}.{}", seed, extension));
+                config.save(&path).unwrap();
+                let loaded = GeneratorConfig::load(&path);
+                std::fs::remove_file(&path).unwrap();
+                assert_eq!(loaded.unwrap().seed(), Some(seed), "{} with seed {}", extension, seed);
+            }
+        }
+    }
+
+    fn gradient_test_configs() -> Vec<GeneratorConfig>
+    {
+        let source = |kind, seed| GeneratorConfig::Source(SourceConfig {
+            kind,
+            seed,
+            values: NoiseValues { octaves: 3, scale: 0.05, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Fbm },
+        });
+        let inputs = vec![
+            WeightedConfig { weight: 0.7, generator: source(SourceKind::Perlin, 1) },
+            WeightedConfig { weight: 1.3, generator: source(SourceKind::Simplex, 2) },
+        ];
+        let mut configs: Vec<GeneratorConfig> = [Combiner::WeightedAverage, Combiner::Sum, Combiner::Multiply, Combiner::Min, Combiner::Max]
+            .into_iter()
+            .map(|combiner| GeneratorConfig::Combined { combiner, inputs: inputs.clone() })
+            .collect();
+        configs.push(GeneratorConfig::DomainWarp {
+            source: Box::new(source(SourceKind::Perlin, 3)),
+            warpers: vec![source(SourceKind::Simplex, 4), source(SourceKind::Perlin, 5)],
+            strength: 6.0,
+            iterations: 2,
+        });
+        return configs;
+    }
+
+    // Central difference in f64 around point, with a step large enough that the f32 rounding of the generators stays small
+    fn central_difference<const N: usize>(sample: impl Fn([f64; N]) -> f64, point: [f64; N]) -> [f64; N]
+    {
+        let step = 1e-3;
+        return std::array::from_fn(|axis| {
+            let mut ahead = point;
+            let mut behind = point;
+            ahead[axis] += step;
+            behind[axis] -= step;
+            (sample(ahead) - sample(behind)) / (2.0 * step)
+        });
+    }
+
+    fn assert_gradient_close<const N: usize>(gradient: [f64; N], expected: [f64; N], point: [f64; N])
+    {
+        for axis in 0..N {
+            assert!((gradient[axis] - expected[axis]).abs() < 5e-4, "gradient {:?} != {:?} at {:?}", gradient, expected, point);
+        }
+    }
+
+    #[test]
+    fn config_gradients_match_central_differences()
+    {
+        for config in gradient_test_configs() {
+            let (source_2d, source_3d) = (config.build_2d(), config.build_3d());
+            for index in 0..40 {
+                let point = [index as f64 * 7.31 - 120.0, index as f64 * 3.17 + 5.5, index as f64 * 1.93 - 30.0];
+
+                let point_2d = [point[0], point[1]];
+                let (value, gradient) = source_2d.sample_with_gradient(point_2d);
+                assert_eq!(value, source_2d.sample(point_2d));
+                assert_gradient_close(gradient, central_difference(|point| source_2d.sample(point), point_2d), point_2d);
+
+                let (value, gradient) = source_3d.sample_with_gradient(point);
+                assert_eq!(value, source_3d.sample(point));
+                assert_gradient_close(gradient, central_difference(|point| source_3d.sample(point), point), point);
+            }
+        }
+    }
+}
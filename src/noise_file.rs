@@ -0,0 +1,951 @@
+use std::{collections::BTreeMap, fmt, fs::File, io::{self, BufReader, BufWriter, Read, Seek, Write}, path::Path, time::{SystemTime, UNIX_EPOCH}};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use half::f16;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::noise_config::GeneratorConfig;
+
+//-------------------------------------------------------------------Defining Noise File Header-------------------------------------------------------------------//
+// Layout of a versioned .noise2d / .noise3d file, all multi byte values little endian:
+//   4 Bytes magic "NOIZ", 2 Bytes version, 1 Byte endianness marker (0 = little endian),
+//   1 Byte data type, 1 Byte dimension count, 8 Bytes per dimension (width, height[, depth]),
+//   4 Bytes metadata length followed by that many bytes of json NoiseMetadata (version 2 onwards, 0 = no metadata),
+//   1 Byte compression (0 = none, 1 = deflate) and 1 Byte filter flags (bit 0 = byte shuffled) (version 3 onwards),
+//   4 Bytes f32 min and 4 Bytes f32 max that u8 / u16 samples are scaled back onto (only for those data types),
+//   8 Bytes f64 origin per dimension and 8 Bytes f64 cell size, the world position of cell 0 and the spacing between cells (version 4 onwards),
+//   followed by the payload, one sample per cell starting from the 0 position in the "buffer".
+// A compressed payload is split into chunks of width * height cells (one per z slice for 3D, a single one for 2D),
+// stored as 8 Bytes compressed length per chunk followed by each chunk's deflate stream, so slices can be decoded on their own.
+// Files written before the header existed start directly with the native endian u64 dimensions
+// and are read back as LEGACY_VERSION.
+pub const NOISE_FILE_MAGIC: [u8; 4] = *b"NOIZ";
+pub const NOISE_FILE_VERSION: u16 = 4;
+pub const LEGACY_VERSION: u16 = 0;
+
+const LITTLE_ENDIAN_MARKER: u8 = 0;
+const SHUFFLE_FLAG: u8 = 1;
+
+// Size and world placement of the grid being saved
+pub struct GridLayout<'a>
+{
+    pub dimensions: &'a [usize],
+    pub origin: &'a [f64],
+    pub cell_size: f64,
+}
+
+// Sample type of the payload, U8 and U16 quantise linearly between the stored min and max
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType
+{
+    F32,
+    U8,
+    U16,
+    F16,
+}
+impl DataType
+{
+    pub fn from_u8(value: u8) -> Option<DataType>
+    {
+        match value {
+            0 => Some(DataType::F32),
+            1 => Some(DataType::U8),
+            2 => Some(DataType::U16),
+            3 => Some(DataType::F16),
+            _ => None,
+        }
+    }
+    pub fn to_u8(self) -> u8
+    {
+        match self {
+            DataType::F32 => 0,
+            DataType::U8 => 1,
+            DataType::U16 => 2,
+            DataType::F16 => 3,
+        }
+    }
+    pub fn size(self) -> usize
+    {
+        match self {
+            DataType::F32 => 4,
+            DataType::U8 => 1,
+            DataType::U16 => 2,
+            DataType::F16 => 2,
+        }
+    }
+    pub fn is_quantised(self) -> bool
+    {
+        matches!(self, DataType::U8 | DataType::U16)
+    }
+}
+
+//-------------------------------------------------------------------Defining Save Options-------------------------------------------------------------------//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression
+{
+    None,
+    Deflate { level: u32 }, // 0 (fastest) to 9 (smallest)
+}
+impl Compression
+{
+    pub fn from_u8(value: u8) -> Option<Compression>
+    {
+        match value {
+            0 => Some(Compression::None),
+            // the level is only needed when writing
+            1 => Some(Compression::Deflate { level: 6 }),
+            _ => None,
+        }
+    }
+    pub fn to_u8(self) -> u8
+    {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate { .. } => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaveOptions
+{
+    pub compression: Compression,
+    // Groups the n-th byte of every sample together before compressing, which usually compresses smooth noise much better
+    pub shuffle: bool,
+    pub data_type: DataType,
+}
+impl Default for SaveOptions
+{
+    fn default() -> SaveOptions
+    {
+        SaveOptions { compression: Compression::None, shuffle: false, data_type: DataType::F32 }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct NoiseFileHeader
+{
+    pub version: u16,
+    pub data_type: DataType,
+    pub dimensions: Vec<u64>,
+    pub metadata: Option<NoiseMetadata>,
+    pub compression: Compression,
+    pub shuffle: bool,
+    pub value_range: (f32, f32),
+    pub origin: Vec<f64>,
+    pub cell_size: f64,
+}
+impl NoiseFileHeader
+{
+    // value_range is what quantised samples are scaled onto, the min and max of the buffer being saved
+    pub fn new(grid: &GridLayout, metadata: Option<NoiseMetadata>, options: &SaveOptions, value_range: (f32, f32)) -> NoiseFileHeader
+    {
+        return NoiseFileHeader {
+            version: NOISE_FILE_VERSION,
+            data_type: options.data_type,
+            dimensions: grid.dimensions.iter().map(|&dimension| dimension as u64).collect(),
+            metadata,
+            compression: options.compression,
+            // shuffling only pays off when compressing
+            shuffle: options.shuffle && options.compression != Compression::None,
+            value_range,
+            origin: grid.origin.to_vec(),
+            cell_size: grid.cell_size,
+        };
+    }
+
+    pub fn is_legacy(&self) -> bool
+    {
+        self.version == LEGACY_VERSION
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()>
+    {
+        writer.write_all(&NOISE_FILE_MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&[LITTLE_ENDIAN_MARKER, self.data_type.to_u8(), self.dimensions.len() as u8])?;
+        for dimension in &self.dimensions {
+            writer.write_all(&dimension.to_le_bytes())?;
+        }
+
+        let metadata_bytes = match &self.metadata {
+            Some(metadata) => serde_json::to_vec(metadata).map_err(io::Error::from)?,
+            None => Vec::new(),
+        };
+        writer.write_all(&(metadata_bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(&metadata_bytes)?;
+
+        let flags = if self.shuffle { SHUFFLE_FLAG } else { 0 };
+        writer.write_all(&[self.compression.to_u8(), flags])?;
+
+        if self.data_type.is_quantised() {
+            writer.write_all(&self.value_range.0.to_le_bytes())?;
+            writer.write_all(&self.value_range.1.to_le_bytes())?;
+        }
+
+        for origin in &self.origin {
+            writer.write_all(&origin.to_le_bytes())?;
+        }
+        writer.write_all(&self.cell_size.to_le_bytes())?;
+        Ok(())
+    }
+
+    // Reads either a versioned header or, if the magic is missing, the bare dimensions of a legacy file
+    pub fn read<R: Read>(reader: &mut R, dimension_count: usize) -> Result<NoiseFileHeader, HeaderError>
+    {
+        let mut magic = [0u8; 4];
+        read_header_bytes(reader, &mut magic)?;
+
+        if magic != NOISE_FILE_MAGIC {
+            // The 4 bytes already read are the first half of the legacy width
+            let mut dimension_bytes = vec![0u8; dimension_count * 8];
+            dimension_bytes[..4].copy_from_slice(&magic);
+            read_header_bytes(reader, &mut dimension_bytes[4..])?;
+
+            let dimensions = dimension_bytes
+                .chunks_exact(8)
+                .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()))
+                .collect();
+            return Ok(NoiseFileHeader {
+                version: LEGACY_VERSION,
+                data_type: DataType::F32,
+                dimensions,
+                metadata: None,
+                compression: Compression::None,
+                shuffle: false,
+                value_range: (0.0, 1.0),
+                origin: vec![0.0; dimension_count],
+                cell_size: 1.0,
+            });
+        }
+
+        let mut fixed = [0u8; 5];
+        read_header_bytes(reader, &mut fixed)?;
+
+        let version = u16::from_le_bytes([fixed[0], fixed[1]]);
+        if version == LEGACY_VERSION || version > NOISE_FILE_VERSION {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+        if fixed[2] != LITTLE_ENDIAN_MARKER {
+            return Err(HeaderError::UnsupportedEndianness(fixed[2]));
+        }
+        let data_type = DataType::from_u8(fixed[3]).ok_or(HeaderError::UnsupportedDataType(fixed[3]))?;
+        if fixed[4] as usize != dimension_count {
+            return Err(HeaderError::DimensionMismatch { expected: dimension_count as u8, found: fixed[4] });
+        }
+
+        let mut dimensions = Vec::with_capacity(dimension_count);
+        for _ in 0..dimension_count {
+            let mut dimension_bytes = [0u8; 8];
+            read_header_bytes(reader, &mut dimension_bytes)?;
+            dimensions.push(u64::from_le_bytes(dimension_bytes));
+        }
+
+        let mut metadata = None;
+        if version >= 2 {
+            let mut length_bytes = [0u8; 4];
+            read_header_bytes(reader, &mut length_bytes)?;
+            let length = u32::from_le_bytes(length_bytes) as u64;
+
+            if length > 0 {
+                // take() keeps a corrupt length from allocating more than the file actually holds
+                let mut metadata_bytes = Vec::new();
+                reader.by_ref().take(length).read_to_end(&mut metadata_bytes).map_err(HeaderError::Io)?;
+                if (metadata_bytes.len() as u64) < length {
+                    return Err(HeaderError::Truncated);
+                }
+                metadata = Some(serde_json::from_slice(&metadata_bytes).map_err(HeaderError::BadMetadata)?);
+            }
+        }
+
+        let mut compression = Compression::None;
+        let mut shuffle = false;
+        if version >= 3 {
+            let mut compression_bytes = [0u8; 2];
+            read_header_bytes(reader, &mut compression_bytes)?;
+            compression = Compression::from_u8(compression_bytes[0]).ok_or(HeaderError::UnsupportedCompression(compression_bytes[0]))?;
+            shuffle = compression_bytes[1] & SHUFFLE_FLAG != 0;
+        }
+
+        let mut value_range = (0.0, 1.0);
+        if data_type.is_quantised() {
+            let mut range_bytes = [0u8; 8];
+            read_header_bytes(reader, &mut range_bytes)?;
+            value_range = (f32::from_le_bytes(range_bytes[..4].try_into().unwrap()), f32::from_le_bytes(range_bytes[4..].try_into().unwrap()));
+        }
+
+        // Older files were always sampled from the origin one unit apart
+        let mut origin = vec![0.0; dimension_count];
+        let mut cell_size = 1.0;
+        if version >= 4 {
+            for value in &mut origin {
+                let mut origin_bytes = [0u8; 8];
+                read_header_bytes(reader, &mut origin_bytes)?;
+                *value = f64::from_le_bytes(origin_bytes);
+            }
+            let mut cell_size_bytes = [0u8; 8];
+            read_header_bytes(reader, &mut cell_size_bytes)?;
+            cell_size = f64::from_le_bytes(cell_size_bytes);
+        }
+
+        Ok(NoiseFileHeader { version, data_type, dimensions, metadata, compression, shuffle, value_range, origin, cell_size })
+    }
+
+    pub fn encode_value(&self, value: f32, bytes: &mut Vec<u8>)
+    {
+        if self.is_legacy() {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+            return;
+        }
+        match self.data_type {
+            DataType::F32 => bytes.extend_from_slice(&value.to_le_bytes()),
+            DataType::U8 => bytes.push((self.quantise(value) * u8::MAX as f32).round() as u8),
+            DataType::U16 => bytes.extend_from_slice(&((self.quantise(value) * u16::MAX as f32).round() as u16).to_le_bytes()),
+            DataType::F16 => bytes.extend_from_slice(&f16::from_f32(value).to_le_bytes()),
+        }
+    }
+
+    // bytes holds exactly one sample of data_type
+    pub fn decode_value(&self, bytes: &[u8]) -> f32
+    {
+        if self.is_legacy() {
+            return f32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+        match self.data_type {
+            DataType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            DataType::U8 => self.dequantise(bytes[0] as f32 / u8::MAX as f32),
+            DataType::U16 => self.dequantise(u16::from_le_bytes(bytes.try_into().unwrap()) as f32 / u16::MAX as f32),
+            DataType::F16 => f16::from_le_bytes(bytes.try_into().unwrap()).to_f32(),
+        }
+    }
+
+    fn quantise(&self, value: f32) -> f32
+    {
+        let (min, max) = self.value_range;
+        if max <= min {
+            return 0.0;
+        }
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    }
+
+    fn dequantise(&self, normalised: f32) -> f32
+    {
+        let (min, max) = self.value_range;
+        min + normalised * (max - min)
+    }
+}
+
+fn read_header_bytes<R: Read>(reader: &mut R, bytes: &mut [u8]) -> Result<(), HeaderError>
+{
+    reader.read_exact(bytes).map_err(|error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => HeaderError::Truncated,
+        _ => HeaderError::Io(error),
+    })
+}
+
+
+//-------------------------------------------------------------------Defining Noise Metadata-------------------------------------------------------------------//
+// Provenance stored alongside a saved map so it can be identified and regenerated later, every field is optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NoiseMetadata
+{
+    pub generator: Option<GeneratorConfig>,
+    pub seed: Option<u64>,
+    pub created: Option<u64>, // seconds since the unix epoch
+    pub tool_version: Option<String>,
+    pub properties: BTreeMap<String, String>,
+}
+impl NoiseMetadata
+{
+    // Stamped with the current time and this crate's version
+    pub fn new() -> NoiseMetadata
+    {
+        return NoiseMetadata {
+            created: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|duration| duration.as_secs()),
+            tool_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..Default::default()
+        };
+    }
+
+    pub fn from_generator(generator: &GeneratorConfig) -> NoiseMetadata
+    {
+        return NoiseMetadata {
+            generator: Some(generator.clone()),
+            seed: generator.seed(),
+            ..NoiseMetadata::new()
+        };
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Header Error-------------------------------------------------------------------//
+#[derive(Debug)]
+pub enum HeaderError
+{
+    Truncated,
+    UnsupportedVersion(u16),
+    UnsupportedEndianness(u8),
+    UnsupportedDataType(u8),
+    UnsupportedCompression(u8),
+    DimensionMismatch { expected: u8, found: u8 },
+    BadMetadata(serde_json::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for HeaderError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            HeaderError::Truncated => write!(f, "file ends before the end of the header"),
+            HeaderError::UnsupportedVersion(version) => write!(f, "unsupported noise file version {}", version),
+            HeaderError::UnsupportedEndianness(marker) => write!(f, "unsupported endianness marker {}", marker),
+            HeaderError::UnsupportedDataType(data_type) => write!(f, "unsupported data type {}", data_type),
+            HeaderError::UnsupportedCompression(compression) => write!(f, "unsupported compression {}", compression),
+            HeaderError::DimensionMismatch { expected, found } => write!(f, "expected a {}D noise file but found a {}D one", expected, found),
+            HeaderError::BadMetadata(error) => write!(f, "invalid metadata block: {}", error),
+            HeaderError::Io(error) => write!(f, "failed to read header: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+
+
+//-------------------------------------------------------------------Defining Noise Map Error-------------------------------------------------------------------//
+#[derive(Debug)]
+pub enum NoiseMapError
+{
+    BadExtension { path: String, expected: &'static str },
+    BadHeader(HeaderError),
+    SizeOverflow { dimensions: Vec<u64>, available: u64 },
+    Truncated { expected: u64, found: u64 },
+    NotMappable(String),
+    SliceOutOfRange { index: usize, depth: usize },
+    CellOutOfRange { x: usize, y: usize, width: usize, height: usize },
+    NoChunkDirectory,
+    ZeroChunkSize,
+    ChunkMismatch { path: String, dimensions: [usize; 3], origin: [f64; 3], chunk_size: usize, expected_origin: [f64; 3] },
+    Png(png::EncodingError),
+    Io(io::Error),
+}
+
+impl fmt::Display for NoiseMapError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            NoiseMapError::BadExtension { path, expected } => write!(f, "{} does not have the .{} extension", path, expected),
+            NoiseMapError::BadHeader(error) => write!(f, "bad header: {}", error),
+            NoiseMapError::SizeOverflow { dimensions, available } => write!(f, "dimensions {:?} need more data than the {} bytes available", dimensions, available),
+            NoiseMapError::Truncated { expected, found } => write!(f, "payload truncated, expected {} bytes but found {}", expected, found),
+            NoiseMapError::NotMappable(path) => write!(f, "{} is compressed and can't be memory mapped", path),
+            NoiseMapError::SliceOutOfRange { index, depth } => write!(f, "slice {} is out of range for a depth of {}", index, depth),
+            NoiseMapError::CellOutOfRange { x, y, width, height } => write!(f, "cell ({}, {}) is out of range for a {}x{} slice", x, y, width, height),
+            NoiseMapError::NoChunkDirectory => write!(f, "chunked map has no directory to save chunks to"),
+            NoiseMapError::ZeroChunkSize => write!(f, "chunked map needs a chunk size of at least 1"),
+            NoiseMapError::ChunkMismatch { path, dimensions, origin, chunk_size, expected_origin } => write!(
+                f,
+                "{} holds a {:?} chunk at {:?}, expected a {} cube at {:?}",
+                path, dimensions, origin, chunk_size, expected_origin
+            ),
+            NoiseMapError::Png(error) => write!(f, "failed to encode png: {}", error),
+            NoiseMapError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for NoiseMapError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self {
+            NoiseMapError::BadHeader(error) => Some(error),
+            NoiseMapError::Png(error) => Some(error),
+            NoiseMapError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<HeaderError> for NoiseMapError
+{
+    fn from(error: HeaderError) -> NoiseMapError
+    {
+        match error {
+            HeaderError::Io(error) => NoiseMapError::Io(error),
+            _ => NoiseMapError::BadHeader(error),
+        }
+    }
+}
+
+impl From<png::EncodingError> for NoiseMapError
+{
+    fn from(error: png::EncodingError) -> NoiseMapError
+    {
+        NoiseMapError::Png(error)
+    }
+}
+
+impl From<io::Error> for NoiseMapError
+{
+    fn from(error: io::Error) -> NoiseMapError
+    {
+        NoiseMapError::Io(error)
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Noise File Reading And Writing-------------------------------------------------------------------//
+pub fn check_extension(path: &str, extension: &'static str) -> Result<(), NoiseMapError>
+{
+    if !Path::new(path).extension().map_or(false, |ext| ext == extension) {
+        return Err(NoiseMapError::BadExtension { path: path.to_string(), expected: extension });
+    }
+    Ok(())
+}
+
+pub fn write_noise_file(path: &str, extension: &'static str, grid: &GridLayout, buffer: &[f32], metadata: Option<&NoiseMetadata>, options: &SaveOptions) -> Result<(), NoiseMapError>
+{
+    check_extension(path, extension)?;
+
+    let mut file = BufWriter::new(File::create(path)?);
+
+    // Write header to the file, quantised types are scaled between the finite min and max of the buffer
+    let value_range = buffer.iter().filter(|value| value.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| (min.min(value), max.max(value)));
+    let value_range = if value_range.0 <= value_range.1 { value_range } else { (0.0, 1.0) };
+    let header = NoiseFileHeader::new(grid, metadata.cloned(), options, value_range);
+    header.write(&mut file)?;
+
+    // Write buffer data to the file
+    match header.compression {
+        Compression::None => {
+            let mut bytes = Vec::with_capacity(buffer.len() * header.data_type.size());
+            for &value in buffer {
+                header.encode_value(value, &mut bytes);
+            }
+            file.write_all(&bytes)?;
+        }
+        Compression::Deflate { level } => {
+            let chunk_cells = grid.dimensions[0] * grid.dimensions[1];
+            let chunks: Vec<Vec<u8>> = if chunk_cells == 0 {
+                Vec::new()
+            } else {
+                buffer
+                    .par_chunks(chunk_cells)
+                    .map(|chunk| compress_chunk(chunk, &header, level))
+                    .collect::<io::Result<_>>()?
+            };
+
+            for chunk in &chunks {
+                file.write_all(&(chunk.len() as u64).to_le_bytes())?;
+            }
+            for chunk in &chunks {
+                file.write_all(chunk)?;
+            }
+        }
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+// Returns the header and the decoded buffer, refusing to allocate more than the file could possibly hold
+pub fn read_noise_file(path: &str, extension: &'static str, dimension_count: usize) -> Result<(NoiseFileHeader, Vec<f32>), NoiseMapError>
+{
+    check_extension(path, extension)?;
+
+    let file = File::open(path)?;
+    let file_length = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+
+    // Read header from the file, headerless files are read as the legacy version
+    let header = NoiseFileHeader::read(&mut file, dimension_count)?;
+    let available = file_length.saturating_sub(file.stream_position()?);
+
+    let buffer = match header.compression {
+        Compression::None => read_raw_payload(&mut file, &header, available)?,
+        Compression::Deflate { .. } => read_compressed_payload(&mut file, &header, available)?,
+    };
+
+    Ok((header, buffer))
+}
+
+fn read_raw_payload<R: Read>(reader: &mut R, header: &NoiseFileHeader, available: u64) -> Result<Vec<f32>, NoiseMapError>
+{
+    let sample_size = header.data_type.size();
+    let cell_count = payload_cell_count(&header.dimensions, sample_size as u64, available)?;
+    let expected = (cell_count * sample_size) as u64;
+
+    // Read the whole payload in one go and decode it across all cores
+    let mut bytes = Vec::with_capacity(expected as usize);
+    reader.by_ref().take(expected).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != expected {
+        return Err(NoiseMapError::Truncated { expected, found: bytes.len() as u64 });
+    }
+
+    Ok(decode_samples(&bytes, header))
+}
+
+fn read_compressed_payload<R: Read>(reader: &mut R, header: &NoiseFileHeader, available: u64) -> Result<Vec<f32>, NoiseMapError>
+{
+    let (chunk_bytes, lengths) = read_chunk_table(reader, header, available)?;
+
+    let mut compressed_chunks = Vec::with_capacity(lengths.len());
+    for &length in &lengths {
+        let mut chunk = vec![0u8; length as usize];
+        reader.read_exact(&mut chunk)?;
+        compressed_chunks.push(chunk);
+    }
+
+    let chunks: Vec<Vec<f32>> = compressed_chunks
+        .par_iter()
+        .map(|chunk| decompress_chunk(chunk, chunk_bytes, header))
+        .collect::<Result<_, _>>()?;
+
+    Ok(chunks.concat())
+}
+
+// Returns the decompressed size of one slice and the compressed length of every slice, the reader is left at the first stream
+pub fn read_chunk_table<R: Read>(reader: &mut R, header: &NoiseFileHeader, available: u64) -> Result<(u64, Vec<u64>), NoiseMapError>
+{
+    let overflow = || NoiseMapError::SizeOverflow { dimensions: header.dimensions.clone(), available };
+
+    let chunk_cells = header.dimensions[..2].iter().try_fold(1u64, |count, &dimension| count.checked_mul(dimension)).ok_or_else(overflow)?;
+    let chunk_count = if chunk_cells == 0 {
+        0
+    } else {
+        header.dimensions[2..].iter().try_fold(1u64, |count, &dimension| count.checked_mul(dimension)).ok_or_else(overflow)?
+    };
+    let chunk_bytes = chunk_cells.checked_mul(header.data_type.size() as u64).ok_or_else(overflow)?;
+
+    // Both the length table and the streams it describes have to fit in what is left of the file,
+    // a table longer than the file means the dimensions are wrong, streams longer than it mean the file was cut off
+    let table_bytes = chunk_count.checked_mul(8).ok_or_else(overflow)?;
+    if table_bytes > available {
+        return Err(overflow());
+    }
+    let mut table = vec![0u8; table_bytes as usize];
+    reader.read_exact(&mut table)?;
+    let lengths: Vec<u64> = table.chunks_exact(8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap())).collect();
+
+    let compressed_bytes = lengths.iter().try_fold(0u64, |total, &length| total.checked_add(length)).ok_or_else(overflow)?;
+    if compressed_bytes > available - table_bytes {
+        return Err(NoiseMapError::Truncated { expected: table_bytes.saturating_add(compressed_bytes), found: available });
+    }
+
+    Ok((chunk_bytes, lengths))
+}
+
+fn compress_chunk(chunk: &[f32], header: &NoiseFileHeader, level: u32) -> io::Result<Vec<u8>>
+{
+    let mut bytes = Vec::with_capacity(chunk.len() * header.data_type.size());
+    for &value in chunk {
+        header.encode_value(value, &mut bytes);
+    }
+    if header.shuffle {
+        bytes = shuffle_bytes(&bytes, header.data_type.size());
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::new(level));
+    encoder.write_all(&bytes)?;
+    encoder.finish()
+}
+
+pub fn decompress_chunk(chunk: &[u8], expected_bytes: u64, header: &NoiseFileHeader) -> Result<Vec<f32>, NoiseMapError>
+{
+    // One byte past the expected size is enough to tell a corrupt stream apart without inflating all of it
+    let mut bytes = Vec::new();
+    DeflateDecoder::new(chunk).take(expected_bytes + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != expected_bytes {
+        return Err(NoiseMapError::Truncated { expected: expected_bytes, found: bytes.len() as u64 });
+    }
+
+    if header.shuffle {
+        bytes = unshuffle_bytes(&bytes, header.data_type.size());
+    }
+    Ok(decode_samples(&bytes, header))
+}
+
+// bytes has to be a whole number of samples of the header's data type
+pub fn decode_samples(bytes: &[u8], header: &NoiseFileHeader) -> Vec<f32>
+{
+    bytes.par_chunks_exact(header.data_type.size()).map(|value| header.decode_value(value)).collect()
+}
+
+// Stores byte 0 of every sample, then byte 1 of every sample and so on
+fn shuffle_bytes(bytes: &[u8], sample_size: usize) -> Vec<u8>
+{
+    let sample_count = bytes.len() / sample_size;
+    let mut shuffled = vec![0u8; bytes.len()];
+    for (index, sample) in bytes.chunks_exact(sample_size).enumerate() {
+        for (byte_index, &byte) in sample.iter().enumerate() {
+            shuffled[byte_index * sample_count + index] = byte;
+        }
+    }
+    shuffled
+}
+
+fn unshuffle_bytes(bytes: &[u8], sample_size: usize) -> Vec<u8>
+{
+    let sample_count = bytes.len() / sample_size;
+    let mut unshuffled = vec![0u8; bytes.len()];
+    for (index, sample) in unshuffled.chunks_exact_mut(sample_size).enumerate() {
+        for (byte_index, byte) in sample.iter_mut().enumerate() {
+            *byte = bytes[byte_index * sample_count + index];
+        }
+    }
+    unshuffled
+}
+
+pub fn payload_cell_count(dimensions: &[u64], sample_size: u64, available: u64) -> Result<usize, NoiseMapError>
+{
+    let overflow = || NoiseMapError::SizeOverflow { dimensions: dimensions.to_vec(), available };
+
+    let cell_count = dimensions.iter().try_fold(1u64, |count, &dimension| count.checked_mul(dimension)).ok_or_else(overflow)?;
+    let byte_count = cell_count.checked_mul(sample_size).ok_or_else(overflow)?;
+    let cell_count = usize::try_from(cell_count).map_err(|_| overflow())?;
+    if byte_count > available {
+        return Err(overflow());
+    }
+
+    Ok(cell_count)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::noise::{NoiseMap2D, NoiseMap3D};
+
+    fn temp_path(name: &str) -> String
+    {
+        return std::env::temp_dir().join(format!("noise_project_file_{}_{}", std::process::id(), name)).to_str().unwrap().to_string();
+    }
+
+    fn cut_off(path: &str, bytes: u64)
+    {
+        let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        let length = file.metadata().unwrap().len();
+        file.set_len(length - bytes).unwrap();
+    }
+
+    // Dimensions that need more than the file holds are an overflow, compressed streams that stop early are a truncation
+    #[test]
+    fn short_payload_is_reported()
+    {
+        let options = [
+            ("raw.noise2d", SaveOptions::default()),
+            ("deflate.noise2d", SaveOptions { compression: Compression::Deflate { level: 6 }, ..Default::default() }),
+        ];
+        for (name, options) in options {
+            let path = temp_path(name);
+            let mut map = NoiseMap2D::from_values(16, 8);
+            map.populate_with(&|point: [f64; 2]| point[0] * 0.1 - point[1]);
+            map.save_with(path.clone(), &options).unwrap();
+            cut_off(&path, 5);
+
+            let result = NoiseMap2D::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            match options.compression {
+                Compression::None => assert!(matches!(result, Err(NoiseMapError::SizeOverflow { .. })), "{}: {:?}", name, result.err()),
+                Compression::Deflate { .. } => assert!(matches!(result, Err(NoiseMapError::Truncated { .. })), "{}: {:?}", name, result.err()),
+            }
+        }
+    }
+
+    // Header followed by a few bytes of payload, whatever the header claims
+    fn write_header_only(path: &str, dimensions: &[usize], options: &SaveOptions)
+    {
+        let grid = GridLayout { dimensions, origin: &vec![0.0; dimensions.len()], cell_size: 1.0 };
+        let header = NoiseFileHeader::new(&grid, None, options, (0.0, 1.0));
+        let mut file = File::create(path).unwrap();
+        header.write(&mut file).unwrap();
+        file.write_all(&[0u8; 16]).unwrap();
+    }
+
+    #[test]
+    fn huge_dimensions_are_a_size_overflow()
+    {
+        let deflate = SaveOptions { compression: Compression::Deflate { level: 6 }, ..Default::default() };
+        let cases = [
+            ("huge.noise2d", vec![1 << 20, 1 << 20], SaveOptions::default()),
+            ("overflowing.noise2d", vec![usize::MAX, 2], SaveOptions::default()),
+            ("huge_deflate.noise3d", vec![4, 4, 1 << 40], deflate),
+        ];
+        for (name, dimensions, options) in cases {
+            let path = temp_path(name);
+            write_header_only(&path, &dimensions, &options);
+            let result = if dimensions.len() == 2 { NoiseMap2D::load(&path).map(|_| ()) } else { NoiseMap3D::load(path.clone()).map(|_| ()) };
+            std::fs::remove_file(&path).unwrap();
+            match result {
+                Err(NoiseMapError::SizeOverflow { dimensions: found, available }) => {
+                    assert_eq!(found, dimensions.iter().map(|&dimension| dimension as u64).collect::<Vec<u64>>());
+                    assert_eq!(available, 16);
+                }
+                other => panic!("{}: {:?}", name, other.err()),
+            }
+        }
+    }
+
+    // Written the way save did before the header existed, native endian dimensions followed by native endian f32s
+    fn write_legacy(path: &str, dimensions: &[u64], buffer: &[f32])
+    {
+        let mut file = File::create(path).unwrap();
+        for dimension in dimensions {
+            file.write_all(&dimension.to_ne_bytes()).unwrap();
+        }
+        for value in buffer {
+            file.write_all(&value.to_ne_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn legacy_files_still_load()
+    {
+        let buffer: Vec<f32> = (0..24).map(|index| index as f32 * 0.25 - 3.0).collect();
+
+        let path = temp_path("legacy.noise2d");
+        write_legacy(&path, &[6, 4], &buffer);
+        let map = NoiseMap2D::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((map.width, map.height), (6, 4));
+        assert_eq!(map.buffer, buffer);
+        assert!(map.metadata.is_none());
+
+        let path = temp_path("legacy.noise3d");
+        write_legacy(&path, &[3, 4, 2], &buffer);
+        let map = NoiseMap3D::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((map.width, map.height, map.depth), (3, 4, 2));
+        assert_eq!(map.buffer, buffer);
+    }
+
+    // Saves a small valid map and overwrites one header byte
+    fn corrupt_header_byte(name: &str, offset: usize, value: u8) -> String
+    {
+        let path = temp_path(name);
+        let mut map = NoiseMap2D::from_values(4, 4);
+        map.populate_with(&|point: [f64; 2]| point[0] - point[1]);
+        map.save(path.clone()).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[offset] = value;
+        std::fs::write(&path, bytes).unwrap();
+        return path;
+    }
+
+    #[test]
+    fn bad_headers_are_typed_errors()
+    {
+        // Without the magic the file is read as a legacy one, whose "dimensions" are now far bigger than the file
+        let path = corrupt_header_byte("bad_magic.noise2d", 0, b'X');
+        let result = NoiseMap2D::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(NoiseMapError::SizeOverflow { .. })), "{:?}", result.err());
+
+        // Data type comes after the magic, the version and the endianness marker
+        let path = corrupt_header_byte("bad_data_type.noise2d", 7, 9);
+        let result = NoiseMap2D::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(NoiseMapError::BadHeader(HeaderError::UnsupportedDataType(9)))), "{:?}", result.err());
+
+        let path = corrupt_header_byte("bad_dimension_count.noise2d", 8, 3);
+        let result = NoiseMap2D::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(NoiseMapError::BadHeader(HeaderError::DimensionMismatch { expected: 2, found: 3 }))), "{:?}", result.err());
+    }
+
+    fn sample_map_3d() -> NoiseMap3D
+    {
+        let mut map = NoiseMap3D::from_values(7, 5, 3);
+        map.populate_with(&|point: [f64; 3]| (point[0] * 0.7).sin() * 2.0 + (point[1] * 0.4).cos() - point[2] * 0.3);
+        return map;
+    }
+
+    #[test]
+    fn metadata_round_trips()
+    {
+        use crate::noise::{FractalMode, NoiseValues};
+        use crate::noise_config::{SourceConfig, SourceKind};
+
+        let values = NoiseValues { octaves: 4, scale: 0.02, persistence: 0.5, lacunarity: 2.0, fractal_mode: FractalMode::Ridged { offset: 1.0, gain: 2.0 } };
+        let generator = GeneratorConfig::Source(SourceConfig { kind: SourceKind::Simplex, seed: u64::MAX, values });
+        let mut metadata = NoiseMetadata::from_generator(&generator);
+        metadata.properties.insert("biome".to_string(), "tundra".to_string());
+
+        let mut map = sample_map_3d();
+        map.metadata = Some(metadata.clone());
+        let options = SaveOptions { compression: Compression::Deflate { level: 6 }, shuffle: true, data_type: DataType::U16 };
+        let path = temp_path("metadata.noise3d");
+        map.save_with(path.clone(), &options).unwrap();
+        let loaded = NoiseMap3D::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.metadata.expect("metadata was saved");
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&metadata).unwrap());
+        assert_eq!(loaded.seed, Some(u64::MAX));
+
+        map.metadata = None;
+        map.save(path.clone()).unwrap();
+        let loaded = NoiseMap3D::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.metadata.is_none());
+    }
+
+    #[test]
+    fn compressed_files_match_uncompressed_ones()
+    {
+        let map = sample_map_3d();
+        let path = temp_path("uncompressed.noise3d");
+        map.save(path.clone()).unwrap();
+        let uncompressed = NoiseMap3D::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(uncompressed.buffer, map.buffer);
+
+        for shuffle in [false, true] {
+            let options = SaveOptions { compression: Compression::Deflate { level: 9 }, shuffle, data_type: DataType::F32 };
+            let path = temp_path(&format!("deflate_{}.noise3d", shuffle));
+            map.save_with(path.clone(), &options).unwrap();
+            let compressed = NoiseMap3D::load(path.clone()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let bits = |buffer: &[f32]| buffer.iter().map(|value| value.to_bits()).collect::<Vec<u32>>();
+            assert_eq!(bits(&compressed.buffer), bits(&uncompressed.buffer), "shuffle {}", shuffle);
+        }
+    }
+
+    #[test]
+    fn quantised_samples_stay_within_half_a_step()
+    {
+        let map = sample_map_3d();
+        let (min, max) = map.buffer.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| (min.min(value), max.max(value)));
+
+        // F16 keeps 11 significant bits, half a step relative to the largest magnitude in the map
+        let f16_step = min.abs().max(max.abs()) / 1024.0;
+        let cases = [(DataType::U8, (max - min) / 255.0), (DataType::U16, (max - min) / 65535.0), (DataType::F16, f16_step)];
+        for (data_type, step) in cases {
+            for compression in [Compression::None, Compression::Deflate { level: 6 }] {
+                let path = temp_path(&format!("quantised_{:?}_{}.noise3d", data_type, compression.to_u8()));
+                map.save_with(path.clone(), &SaveOptions { compression, shuffle: true, data_type }).unwrap();
+                let loaded = NoiseMap3D::load(path.clone()).unwrap();
+                std::fs::remove_file(&path).unwrap();
+
+                let error = loaded.buffer.iter().zip(&map.buffer).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+                // A little slack on top of half a step for the f32 rounding of the scale itself
+                assert!(error <= step * 0.5 * 1.001, "{:?}: error {} against a step of {}", data_type, error, step);
+            }
+
+            // min == max leaves nothing to scale by, every cell has to come back as that value
+            let mut flat = NoiseMap2D::from_values(4, 3);
+            flat.buffer.iter_mut().for_each(|value| *value = -0.625);
+            let path = temp_path(&format!("flat_{:?}.noise2d", data_type));
+            flat.save_with(path.clone(), &SaveOptions { data_type, ..Default::default() }).unwrap();
+            let loaded = NoiseMap2D::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.buffer, flat.buffer, "{:?}", data_type);
+        }
+    }
+}
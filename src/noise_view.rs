@@ -0,0 +1,263 @@
+use std::{fs::File, io::{BufReader, Read, Seek, SeekFrom}, ops::Range};
+
+use bevy::prelude::*;
+use memmap2::Mmap;
+
+use crate::noise::NoiseMap2D;
+use crate::noise_file::{check_extension, decode_samples, decompress_chunk, payload_cell_count, read_chunk_table, Compression, NoiseFileHeader, NoiseMapError, NoiseMetadata};
+
+//-------------------------------------------------------------------Defining Noise Map 3D View-------------------------------------------------------------------//
+// Read only view of an uncompressed .noise3d file, values are decoded straight out of the mapped file so only the pages touched are ever read
+pub struct NoiseMap3DView
+{
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    header: NoiseFileHeader,
+    payload_offset: usize,
+    mmap: Mmap,
+}
+impl NoiseMap3DView
+{
+    pub fn open(path: &str) -> Result<NoiseMap3DView, NoiseMapError>
+    {
+        check_extension(path, "noise3d")?;
+
+        let file = File::open(path)?;
+        // Safety: the map is only ever read, a file truncated by another process while mapped is outside what we can guard against
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        // Whatever the header read leaves of the slice is the payload
+        let mut remaining: &[u8] = &mmap;
+        let header = NoiseFileHeader::read(&mut remaining, 3)?;
+        if header.compression != Compression::None {
+            return Err(NoiseMapError::NotMappable(path.to_string()));
+        }
+        let payload_offset = mmap.len() - remaining.len();
+        payload_cell_count(&header.dimensions, header.data_type.size() as u64, remaining.len() as u64)?;
+
+        let width = header.dimensions[0] as usize;
+        let height = header.dimensions[1] as usize;
+        let depth = header.dimensions[2] as usize;
+
+        Ok(NoiseMap3DView { width, height, depth, header, payload_offset, mmap })
+    }
+
+    pub fn metadata(&self) -> Option<&NoiseMetadata>
+    {
+        return self.header.metadata.as_ref();
+    }
+
+    fn slice_origin(&self) -> [f64; 2]
+    {
+        return [self.header.origin[0], self.header.origin[1]];
+    }
+
+    // Out of range coordinates are errors rather than reads of whatever lies next to them in the file
+    pub fn get_value_at(&self, x: u32, y: u32, z: u32) -> Result<f32, NoiseMapError>
+    {
+        self.check_slice(z as usize)?;
+        if x as usize >= self.width || y as usize >= self.height {
+            return Err(NoiseMapError::CellOutOfRange { x: x as usize, y: y as usize, width: self.width, height: self.height });
+        }
+        let index = (z as usize * self.height * self.width) + (y as usize * self.width) + x as usize;
+        let sample_size = self.header.data_type.size();
+        let start = self.payload_offset + index * sample_size;
+        return Ok(self.header.decode_value(&self.mmap[start..start + sample_size]));
+    }
+
+    pub fn get_slice_z(&self, z_level: u32) -> Result<NoiseMap2D, NoiseMapError>
+    {
+        self.check_slice(z_level as usize)?;
+        let slice_bytes = self.width * self.height * self.header.data_type.size();
+        let start = self.payload_offset + z_level as usize * slice_bytes;
+        let buffer = decode_samples(&self.mmap[start..start + slice_bytes], &self.header);
+
+        return Ok(NoiseMap2D{ width: self.width, height: self.height, buffer, metadata: None, origin: self.slice_origin(), cell_size: self.header.cell_size });
+    }
+
+    // Same check as NoiseMap3DReader::read_slice_z so both ways of reading a file fail alike
+    fn check_slice(&self, z_level: usize) -> Result<(), NoiseMapError>
+    {
+        if z_level >= self.depth {
+            return Err(NoiseMapError::SliceOutOfRange { index: z_level, depth: self.depth });
+        }
+        Ok(())
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Noise Map 3D Reader-------------------------------------------------------------------//
+// Decodes single z slices of a .noise3d file on demand, compressed files are seeked through with the per slice length table
+#[derive(Resource)]
+pub struct NoiseMap3DReader
+{
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    header: NoiseFileHeader,
+    // (file offset, stored length) of every z slice
+    slices: Vec<(u64, u64)>,
+    slice_bytes: u64,
+    file: BufReader<File>,
+}
+impl NoiseMap3DReader
+{
+    pub fn open(path: &str) -> Result<NoiseMap3DReader, NoiseMapError>
+    {
+        check_extension(path, "noise3d")?;
+
+        let file = File::open(path)?;
+        let file_length = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+
+        let header = NoiseFileHeader::read(&mut file, 3)?;
+        let available = file_length.saturating_sub(file.stream_position()?);
+
+        let width = header.dimensions[0] as usize;
+        let height = header.dimensions[1] as usize;
+        let depth = header.dimensions[2] as usize;
+
+        let (slice_bytes, slices) = match header.compression {
+            Compression::None => {
+                payload_cell_count(&header.dimensions, header.data_type.size() as u64, available)?;
+                let payload_offset = file.stream_position()?;
+                let slice_bytes = (width * height * header.data_type.size()) as u64;
+                let slices = (0..depth as u64).map(|z| (payload_offset + z * slice_bytes, slice_bytes)).collect();
+                (slice_bytes, slices)
+            }
+            Compression::Deflate { .. } => {
+                let (slice_bytes, lengths) = read_chunk_table(&mut file, &header, available)?;
+                let mut offset = file.stream_position()?;
+                let slices = lengths.iter().map(|&length| {
+                    let slice = (offset, length);
+                    offset += length;
+                    slice
+                }).collect();
+                (slice_bytes, slices)
+            }
+        };
+
+        Ok(NoiseMap3DReader { width, height, depth, header, slices, slice_bytes, file })
+    }
+
+    pub fn metadata(&self) -> Option<&NoiseMetadata>
+    {
+        return self.header.metadata.as_ref();
+    }
+
+    fn slice_origin(&self) -> [f64; 2]
+    {
+        return [self.header.origin[0], self.header.origin[1]];
+    }
+
+    pub fn read_slice_z(&mut self, z_level: usize) -> Result<NoiseMap2D, NoiseMapError>
+    {
+        if z_level >= self.depth {
+            return Err(NoiseMapError::SliceOutOfRange { index: z_level, depth: self.depth });
+        }
+        // A zero sized slice has no entry in the length table of a compressed file
+        let Some(&(offset, length)) = self.slices.get(z_level) else {
+            return Ok(NoiseMap2D{ width: self.width, height: self.height, buffer: Vec::new(), metadata: None, origin: self.slice_origin(), cell_size: self.header.cell_size });
+        };
+
+        let mut bytes = vec![0u8; length as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut bytes)?;
+
+        let buffer = match self.header.compression {
+            Compression::None => decode_samples(&bytes, &self.header),
+            Compression::Deflate { .. } => decompress_chunk(&bytes, self.slice_bytes, &self.header)?,
+        };
+
+        return Ok(NoiseMap2D{ width: self.width, height: self.height, buffer, metadata: None, origin: self.slice_origin(), cell_size: self.header.cell_size });
+    }
+
+    pub fn read_slices_z(&mut self, z_levels: Range<usize>) -> Result<Vec<NoiseMap2D>, NoiseMapError>
+    {
+        return z_levels.map(|z_level| self.read_slice_z(z_level)).collect();
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::noise::NoiseMap3D;
+    use crate::noise_file::{DataType, SaveOptions};
+
+    fn temp_path(name: &str) -> String
+    {
+        return std::env::temp_dir().join(format!("noise_project_view_{}_{}", std::process::id(), name)).to_str().unwrap().to_string();
+    }
+
+    fn sample_map() -> NoiseMap3D
+    {
+        let mut map = NoiseMap3D::from_values(6, 4, 5);
+        map.populate_with(&|point: [f64; 3]| (point[0] * 0.9).sin() + point[1] * 0.25 - (point[2] * 0.6).cos());
+        return map;
+    }
+
+    #[test]
+    fn view_matches_load()
+    {
+        let map = sample_map();
+        for data_type in [DataType::F32, DataType::U16] {
+            let path = temp_path(&format!("view_{:?}.noise3d", data_type));
+            map.save_with(path.clone(), &SaveOptions { data_type, ..Default::default() }).unwrap();
+            let loaded = NoiseMap3D::load(path.clone()).unwrap();
+            let view = NoiseMap3DView::open(&path).unwrap();
+
+            assert_eq!((view.width, view.height, view.depth), (6, 4, 5));
+            for z in 0..5 {
+                for y in 0..4 {
+                    for x in 0..6 {
+                        assert_eq!(view.get_value_at(x, y, z).unwrap(), loaded.get_value_at(x, y, z));
+                    }
+                }
+                assert_eq!(view.get_slice_z(z).unwrap().buffer, loaded.get_slice_z(z).buffer);
+            }
+
+            assert!(matches!(view.get_slice_z(5), Err(NoiseMapError::SliceOutOfRange { index: 5, depth: 5 })));
+            assert!(matches!(view.get_value_at(0, 0, 5), Err(NoiseMapError::SliceOutOfRange { index: 5, depth: 5 })));
+            assert!(matches!(view.get_value_at(6, 0, 0), Err(NoiseMapError::CellOutOfRange { .. })));
+            assert!(matches!(view.get_value_at(0, 4, 0), Err(NoiseMapError::CellOutOfRange { .. })));
+            drop(view);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn reader_slices_match_get_slice_z()
+    {
+        let map = sample_map();
+        let options = [
+            ("reader_raw.noise3d", SaveOptions::default()),
+            ("reader_deflate.noise3d", SaveOptions { compression: Compression::Deflate { level: 6 }, shuffle: true, data_type: DataType::F32 }),
+        ];
+        for (name, options) in options {
+            let path = temp_path(name);
+            map.save_with(path.clone(), &options).unwrap();
+            let mut reader = NoiseMap3DReader::open(&path).unwrap();
+
+            assert_eq!((reader.width, reader.height, reader.depth), (6, 4, 5));
+            // Read out of order so every slice is found by seeking rather than by following on from the last one
+            for z in [3, 0, 4, 1, 2] {
+                let slice = reader.read_slice_z(z).unwrap();
+                assert_eq!((slice.width, slice.height), (6, 4));
+                assert_eq!(slice.buffer, map.get_slice_z(z as u32).buffer, "{} slice {}", name, z);
+            }
+            let slices = reader.read_slices_z(1..4).unwrap();
+            assert_eq!(slices.len(), 3);
+            for (slice, z) in slices.iter().zip(1..4) {
+                assert_eq!(slice.buffer, map.get_slice_z(z).buffer);
+            }
+
+            assert!(matches!(reader.read_slice_z(5), Err(NoiseMapError::SliceOutOfRange { index: 5, depth: 5 })));
+            assert!(matches!(reader.read_slices_z(3..6), Err(NoiseMapError::SliceOutOfRange { index: 5, depth: 5 })));
+            drop(reader);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
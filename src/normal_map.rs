@@ -0,0 +1,224 @@
+use std::{fs::File, io::BufWriter};
+
+use bevy::{prelude::*, render::{texture::ImageSampler, render_resource::{TextureDescriptor, TextureDimension, TextureFormat, Extent3d, TextureUsages}}};
+use rayon::prelude::*;
+
+use crate::noise::NoiseMap2D;
+use crate::noise_file::NoiseMapError;
+
+//-------------------------------------------------------------------Defining Normal Map Settings-------------------------------------------------------------------//
+// Finite difference kernels for the height slope, Sobel and Scharr also average over the neighbouring rows / columns to smooth out noise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalKernel
+{
+    CentralDifference,
+    Sobel,
+    Scharr,
+}
+impl NormalKernel
+{
+    // Weights of the rows either side of the centre and of the centre row, across the slope direction
+    fn weights(self) -> [f32; 3]
+    {
+        match self {
+            NormalKernel::CentralDifference => [0.0, 1.0, 0.0],
+            NormalKernel::Sobel => [1.0, 2.0, 1.0],
+            NormalKernel::Scharr => [3.0, 10.0, 3.0],
+        }
+    }
+}
+
+// What a kernel reads past the edge of the map, Wrap suits tileable maps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeMode
+{
+    Wrap,
+    Clamp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalMapSettings
+{
+    pub kernel: NormalKernel,
+    // Multiplies the slopes, higher values give bumpier looking normals
+    pub strength: f32,
+    pub edge_mode: EdgeMode,
+    // Points y towards the bottom of the image instead (DirectX convention), which inverts the green channel
+    pub flip_y: bool,
+}
+impl Default for NormalMapSettings
+{
+    fn default() -> NormalMapSettings
+    {
+        NormalMapSettings { kernel: NormalKernel::Sobel, strength: 1.0, edge_mode: EdgeMode::Clamp, flip_y: false }
+    }
+}
+
+
+//-------------------------------------------------------------------Defining Normal Map-------------------------------------------------------------------//
+// Tangent space normals, one per cell of the source map, z out of the surface and y towards the top of the image (OpenGL / Bevy convention)
+#[derive(Clone, Debug)]
+pub struct NormalMap
+{
+    pub width: usize,
+    pub height: usize,
+    pub normals: Vec<[f32; 3]>,
+}
+impl NormalMap
+{
+    pub fn from_height_map(map: &NoiseMap2D, settings: &NormalMapSettings) -> NormalMap
+    {
+        let (width, height) = (map.width, map.height);
+        let mut normals = vec![[0.0, 0.0, 1.0]; width * height];
+        if width == 0 {
+            return NormalMap { width, height, normals };
+        }
+
+        let sample = |x: isize, y: isize| -> f32 {
+            let (x, y) = match settings.edge_mode {
+                EdgeMode::Wrap => (x.rem_euclid(width as isize), y.rem_euclid(height as isize)),
+                EdgeMode::Clamp => (x.clamp(0, width as isize - 1), y.clamp(0, height as isize - 1)),
+            };
+            return map.get_value_at(x as u32, y as u32);
+        };
+        let weights = settings.kernel.weights();
+        let weight_sum: f32 = weights.iter().sum();
+
+        normals.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            let y = y as isize;
+            for (x, normal) in row.iter_mut().enumerate() {
+                let x = x as isize;
+                let mut slope_x = 0.0;
+                let mut slope_y = 0.0;
+                for (offset, weight) in (-1..=1).zip(weights) {
+                    slope_x += weight * (sample(x + 1, y + offset) - sample(x - 1, y + offset));
+                    slope_y += weight * (sample(x + offset, y + 1) - sample(x + offset, y - 1));
+                }
+                // Averaged over the kernel and per cell, rows grow downwards so the y slope flips for a y up normal
+                let slope_x = slope_x / (2.0 * weight_sum) * settings.strength;
+                let slope_y = slope_y / (2.0 * weight_sum) * settings.strength;
+                let slope_y = if settings.flip_y { -slope_y } else { slope_y };
+                *normal = Vec3::new(-slope_x, slope_y, 1.0).normalize().to_array();
+            }
+        });
+
+        return NormalMap { width, height, normals };
+    }
+
+    // Each component mapped from -1..1 onto 0..255
+    fn encode(normal: [f32; 3]) -> [u8; 3]
+    {
+        return normal.map(|component| ((component * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8);
+    }
+
+    pub fn to_image(&self) -> Image
+    {
+        let mut image = Image {
+            data: Vec::with_capacity(self.normals.len() * 4),
+            texture_descriptor: TextureDescriptor {
+                label: None,
+                size: Extent3d{width: self.width as u32, height: self.height as u32, depth_or_array_layers: 1},
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::all(),
+                view_formats: &[TextureFormat::Rgba8Unorm],
+            },
+            sampler: ImageSampler::default(),
+            texture_view_descriptor: None,
+        };
+
+        for &normal in &self.normals {
+            image.data.extend_from_slice(&NormalMap::encode(normal));
+            image.data.push(255); // Alpha channel
+        }
+
+        image
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), NoiseMapError>
+    {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.normals.iter().flat_map(|&normal| NormalMap::encode(normal)).collect();
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn plane(slope_x: f32, slope_y: f32) -> NoiseMap2D
+    {
+        let mut map = NoiseMap2D::from_values(8, 6);
+        map.populate_with(&|point: [f64; 2]| point[0] * slope_x as f64 + point[1] * slope_y as f64);
+        return map;
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3])
+    {
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn flat_map_points_straight_out()
+    {
+        for edge_mode in [EdgeMode::Wrap, EdgeMode::Clamp] {
+            let normals = plane(0.0, 0.0).normal_map(&NormalMapSettings { edge_mode, ..Default::default() });
+            assert!(normals.normals.iter().all(|&normal| normal == [0.0, 0.0, 1.0]));
+            assert_eq!(NormalMap::encode([0.0, 0.0, 1.0]), [128, 128, 255]);
+            assert!(normals.to_image().data.chunks_exact(4).all(|pixel| pixel == [128, 128, 255, 255]));
+        }
+    }
+
+    #[test]
+    fn slopes_tilt_the_normal_away_from_uphill()
+    {
+        // Height rising along +x tilts the normal towards -x, rising along +y (down the image) tilts it up the image
+        let normals = plane(0.5, 0.0).normal_map(&NormalMapSettings::default());
+        let expected = Vec3::new(-0.5, 0.0, 1.0).normalize().to_array();
+        assert_close(normals.normals[2 * 8 + 3], expected);
+
+        let settings = NormalMapSettings { kernel: NormalKernel::CentralDifference, ..Default::default() };
+        let normals = plane(0.0, 0.25).normal_map(&settings);
+        let flipped = plane(0.0, 0.25).normal_map(&NormalMapSettings { flip_y: true, ..settings });
+        let normal = normals.normals[2 * 8 + 3];
+        assert!(normal[1] > 0.0);
+        assert_close(flipped.normals[2 * 8 + 3], [normal[0], -normal[1], normal[2]]);
+
+        // Only green changes, mirrored around the middle of the channel
+        let (image, flipped_image) = (normals.to_image(), flipped.to_image());
+        for (pixel, flipped_pixel) in image.data.chunks_exact(4).zip(flipped_image.data.chunks_exact(4)) {
+            assert_eq!([pixel[0], pixel[2], pixel[3]], [flipped_pixel[0], flipped_pixel[2], flipped_pixel[3]]);
+            assert_eq!(pixel[1] as u32 + flipped_pixel[1] as u32, 255);
+        }
+    }
+
+    #[test]
+    fn kernels_agree_on_a_plane()
+    {
+        let map = plane(0.3, -0.7);
+        let central = map.normal_map(&NormalMapSettings { kernel: NormalKernel::CentralDifference, ..Default::default() });
+        for kernel in [NormalKernel::Sobel, NormalKernel::Scharr] {
+            let normals = map.normal_map(&NormalMapSettings { kernel, ..Default::default() });
+            // Away from the clamped border every kernel sees the same plane
+            for y in 1..5 {
+                for x in 1..7 {
+                    assert_close(normals.normals[y * 8 + x], central.normals[y * 8 + x]);
+                }
+            }
+        }
+    }
+}